@@ -0,0 +1,184 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::generate::Generated;
+
+/// The name of the cache directory in the data dir.
+pub const CACHE_DIR: &str = "cache";
+
+/// How long cached commands are reused for, unless the config file says otherwise.
+pub const DEFAULT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Generated commands stored on disk, one file per prompt.
+///
+/// The cache is best effort: unreadable or corrupt entries are treated as misses.
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    /// The full key, to guard against hash collisions between file names.
+    key: String,
+    created_at: u64,
+    generated: Vec<Generated>,
+}
+
+/// A summary of what is in the cache.
+pub struct Stats {
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Cache {
+        Cache { dir, ttl }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the commands cached for a key, if there are any and they have not expired.
+    pub async fn get(&self, key: &str) -> Option<Vec<Generated>> {
+        let path = self.path(key);
+        let entry = read_entry(&path).await?;
+
+        if entry.key != key {
+            return None;
+        }
+        if self.is_expired(&entry) {
+            let _ = tokio::fs::remove_file(&path).await;
+            return None;
+        }
+
+        Some(entry.generated)
+    }
+
+    pub async fn put(&self, key: &str, generated: &[Generated]) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("Unable to create {}", self.dir.display()))?;
+
+        let entry = serde_json::to_vec(&EntryRef {
+            key,
+            created_at: now(),
+            generated,
+        })?;
+
+        let path = self.path(key);
+        tokio::fs::write(&path, entry)
+            .await
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    /// Removes every entry, returning how many there were.
+    pub async fn clear(&self) -> Result<usize> {
+        let mut removed = 0;
+        for path in self.entry_paths().await? {
+            tokio::fs::remove_file(&path)
+                .await
+                .with_context(|| format!("Unable to remove {}", path.display()))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    pub async fn stats(&self) -> Result<Stats> {
+        let mut stats = Stats {
+            entries: 0,
+            expired: 0,
+            bytes: 0,
+        };
+
+        for path in self.entry_paths().await? {
+            stats.entries += 1;
+            if let Ok(metadata) = tokio::fs::metadata(&path).await {
+                stats.bytes += metadata.len();
+            }
+            match read_entry(&path).await {
+                Some(entry) if !self.is_expired(&entry) => {}
+                _ => stats.expired += 1,
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+    }
+
+    fn is_expired(&self, entry: &Entry) -> bool {
+        now().saturating_sub(entry.created_at) >= self.ttl.as_secs()
+    }
+
+    async fn entry_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Unable to read {}", self.dir.display()))
+            }
+        };
+
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+}
+
+/// Borrowed form of `Entry`, so writing does not need to clone the commands.
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    key: &'a str,
+    created_at: u64,
+    generated: &'a [Generated],
+}
+
+/// Builds a cache key from everything that shapes the response.
+///
+/// The action is normalised so trivial differences in case and spacing still hit the cache.
+pub fn key(action: &str, parts: &[(&str, String)]) -> String {
+    let action = action.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let mut key = format!("action={}", action.to_lowercase());
+    for (name, value) in parts {
+        key.push_str(&format!("\n{}={}", name, value));
+    }
+    key
+}
+
+async fn read_entry(path: &Path) -> Option<Entry> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// A stable hash for file names. `DefaultHasher` is not guaranteed to be stable across releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
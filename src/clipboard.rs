@@ -0,0 +1,112 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::environment::is_on_path;
+
+/// Clipboard programs, the environment variable that must be set for each to work, and their
+/// arguments.
+const TOOLS: &[(&str, Option<&str>, &[&str])] = &[
+    ("wl-copy", Some("WAYLAND_DISPLAY"), &[]),
+    ("xclip", Some("DISPLAY"), &["-selection", "clipboard"]),
+    ("xsel", Some("DISPLAY"), &["--clipboard", "--input"]),
+    ("pbcopy", None, &[]),
+];
+
+/// Places text on the system clipboard.
+///
+/// An OSC 52 escape sequence is written to the terminal, which works over SSH in terminals that
+/// support it. A local clipboard program is used as well when one is available, since many
+/// terminals ignore OSC 52. Fails only if neither is possible.
+pub fn copy(text: &str) -> Result<()> {
+    let osc52 = copy_osc52(text)?;
+
+    match copy_with_tool(text) {
+        Ok(true) => Ok(()),
+        // The terminal may well have taken the text, so a failing program is not an error.
+        Ok(false) | Err(_) if osc52 => Ok(()),
+        Ok(false) => anyhow::bail!(
+            "Unable to copy to the clipboard. Run howto in a terminal, or install wl-copy, xclip or xsel."
+        ),
+        Err(err) => Err(err),
+    }
+}
+
+fn copy_osc52(text: &str) -> Result<bool> {
+    let mut stderr = io::stderr();
+    if !stderr.is_terminal() {
+        return Ok(false);
+    }
+
+    let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    // tmux only forwards escape sequences to the outer terminal when wrapped in a passthrough.
+    let sequence = if env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    };
+
+    stderr
+        .write_all(sequence.as_bytes())
+        .and_then(|_| stderr.flush())
+        .context("Unable to write to the terminal")?;
+    Ok(true)
+}
+
+fn copy_with_tool(text: &str) -> Result<bool> {
+    let tool = TOOLS.iter().find(|(program, env_var, _)| {
+        let usable = match env_var {
+            Some(env_var) => env::var_os(env_var).is_some(),
+            None => cfg!(target_os = "macos"),
+        };
+        usable && is_on_path(program)
+    });
+    let Some((program, _, args)) = tool else {
+        return Ok(false);
+    };
+
+    let mut child = Command::new(program)
+        .args(*args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Unable to run {}", program))?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())
+        .with_context(|| format!("Unable to write to {}", program))?;
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Unable to run {}", program))?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(true)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
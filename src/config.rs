@@ -0,0 +1,545 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Table, TableLike, Value};
+
+use crate::provider::ProviderKind;
+
+/// The name of the config file in the data dir.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings read from `config.toml` in the data dir.
+///
+/// Every field is optional. Command line flags and environment variables take precedence over
+/// anything set here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The profile used when `--profile` is not given.
+    pub profile: Option<String>,
+    pub provider: Option<ProviderKind>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub api_base: Option<String>,
+    /// A command that prints the API key of `provider`, e.g. `pass show openai`. Run once per
+    /// invocation.
+    pub api_key_command: Option<String>,
+    /// The OpenAI organization ID sent with every request.
+    pub org_id: Option<String>,
+    /// The OpenAI project ID sent with every request.
+    pub project_id: Option<String>,
+    /// Extra HTTP headers sent with every request, e.g. for a gateway.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub proxy: Option<String>,
+    /// How long to wait for each request to the provider, in seconds.
+    pub request_timeout: Option<u64>,
+    /// How long to wait for a command, including retries, in seconds. Unlimited by default.
+    pub timeout: Option<u64>,
+    /// Whether to have the provider return JSON matching a schema rather than tagged text.
+    /// Defaults to true, except for the openai-compatible provider.
+    pub structured_output: Option<bool>,
+    /// The shell used by `--run`. Defaults to `$SHELL`.
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// A model on this machine, used by `--offline` and when the provider cannot be reached.
+    pub offline: Option<OfflineConfig>,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub recipes: RecipesConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub prompt: PromptConfig,
+    /// Named bundles of settings, selected with `--profile`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+/// Settings that replace the top-level ones when the profile is selected.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub provider: Option<ProviderKind>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub api_base: Option<String>,
+    pub api_key_command: Option<String>,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SafetyConfig {
+    pub policy: Option<SafetyPolicy>,
+}
+
+/// What to do with commands the safety analyser flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SafetyPolicy {
+    /// Warn about risky commands and refuse to `--run` high risk ones without `--force`.
+    #[default]
+    Block,
+    /// Warn about risky commands but run them if asked.
+    Warn,
+    /// Skip the safety analysis entirely.
+    Off,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The bare command on stdout.
+    #[default]
+    Text,
+    /// A JSON object with the command, explanation, risk and token usage.
+    Json,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OfflineConfig {
+    /// ollama or openai-compatible, e.g. for llama.cpp's server. Defaults to ollama.
+    pub provider: Option<ProviderKind>,
+    pub model: Option<String>,
+    pub api_base: Option<String>,
+    /// Whether to use the local model when the provider cannot be reached. Defaults to true.
+    pub fallback: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// How many times to send a request before giving up, including the first. Defaults to 3.
+    pub max_attempts: Option<u32>,
+    /// The delay before the first retry, in seconds. It doubles after each attempt. Defaults to 1.
+    pub initial_delay: Option<f64>,
+    /// The longest delay between attempts, in seconds. Defaults to 30.
+    pub max_delay: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecipesConfig {
+    /// Whether to answer actions that match a recipe without asking the model. Defaults to true.
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Whether to reuse commands generated for the same action. Defaults to true.
+    pub enabled: Option<bool>,
+    /// How long cached commands are reused for, in seconds. Defaults to a week.
+    pub ttl: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Whether to save queries to the history file. Defaults to true.
+    pub enabled: Option<bool>,
+    /// How many entries to keep. Defaults to 1000.
+    pub max_entries: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptConfig {
+    /// Replaces the built-in system prompt entirely.
+    pub system: Option<String>,
+    /// Appended to the system prompt, e.g. "Prefer ripgrep over grep."
+    pub extra: Option<String>,
+    /// Whether to describe the local OS, shell and installed tools to the model. Defaults to true.
+    pub environment: Option<bool>,
+}
+
+impl Config {
+    /// Reads the config file, returning the default config if it does not exist.
+    pub async fn load(path: &Path) -> Result<Config> {
+        let table = read_table(path).await?;
+        from_table(table).with_context(|| format!("Unable to parse config in {}", path.display()))
+    }
+
+    /// The key command to use for a provider. It prints the key of the provider configured
+    /// beside it, so a different provider picked with `--provider` or `HOWTO_CLI_PROVIDER` looks
+    /// for its key elsewhere rather than being sent another vendor's.
+    pub fn api_key_command(&self, kind: ProviderKind) -> Option<&str> {
+        let configured = self.provider.unwrap_or(ProviderKind::OpenAI);
+        self.api_key_command
+            .as_deref()
+            .filter(|_| kind == configured && kind.uses_api_key())
+    }
+
+    /// Replaces the top-level settings with those of a profile, returning whether it is defined.
+    /// A profile need not be defined, as it may only be used to keep a separate set of API keys.
+    ///
+    /// A profile that picks its own provider does not inherit the top-level model, API base or
+    /// key command, since they were meant for a different provider.
+    pub fn apply_profile(&mut self, name: &str) -> bool {
+        let Some(profile) = self.profiles.remove(name) else {
+            return false;
+        };
+
+        if profile.provider.is_some() {
+            self.provider = profile.provider;
+            self.model = profile.model;
+            self.api_base = profile.api_base;
+            self.api_key_command = profile.api_key_command;
+        } else {
+            self.model = profile.model.or(self.model.take());
+            self.api_base = profile.api_base.or(self.api_base.take());
+            self.api_key_command = profile.api_key_command.or(self.api_key_command.take());
+        }
+        self.temperature = profile.temperature.or(self.temperature);
+        self.max_tokens = profile.max_tokens.or(self.max_tokens);
+        self.safety.policy = profile.safety.policy.or(self.safety.policy);
+        true
+    }
+}
+
+fn from_table(table: toml::Table) -> Result<Config> {
+    Ok(toml::Value::Table(table).try_into()?)
+}
+
+fn from_document(document: &DocumentMut) -> Result<Config> {
+    from_table(document.to_string().parse()?)
+}
+
+/// The contents of the config file, which is empty if there is no file yet.
+async fn read_file(path: &Path) -> Result<String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => Ok(contents),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => {
+            Err(err).with_context(|| format!("Unable to read config from {}", path.display()))
+        }
+    }
+}
+
+async fn read_table(path: &Path) -> Result<toml::Table> {
+    read_file(path)
+        .await?
+        .parse()
+        .with_context(|| format!("Unable to parse config in {}", path.display()))
+}
+
+/// Reads the config file for editing, keeping its comments and layout.
+async fn read_document(path: &Path) -> Result<DocumentMut> {
+    read_file(path)
+        .await?
+        .parse()
+        .with_context(|| format!("Unable to parse config in {}", path.display()))
+}
+
+async fn write_document(path: &Path, document: &DocumentMut) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Unable to create {}", parent.display()))?;
+    }
+
+    tokio::fs::write(path, document.to_string())
+        .await
+        .with_context(|| format!("Unable to write config to {}", path.display()))
+}
+
+/// Returns the value of a dotted key such as `safety.policy`, if it is set.
+pub async fn get(path: &Path, key: &str) -> Result<Option<toml::Value>> {
+    let table = read_table(path).await?;
+    let mut value = None;
+    let mut current = Some(&table);
+
+    for part in key.split('.') {
+        value = current.and_then(|table| table.get(part));
+        current = value.and_then(toml::Value::as_table);
+    }
+
+    Ok(value.cloned())
+}
+
+/// Sets a dotted key, validating the resulting config before writing it. The rest of the file,
+/// comments included, is left as it was.
+///
+/// The value is parsed as TOML where possible, so `0.2` becomes a float and `false` a boolean,
+/// and falls back to a plain string so `gpt-4o` needs no quoting.
+pub async fn set(path: &Path, key: &str, value: &str) -> Result<()> {
+    let document = read_document(path).await?;
+
+    let typed = parse_value(value);
+    let candidates = typed.into_iter().chain(std::iter::once(Value::from(value)));
+
+    let mut last_error = None;
+    for candidate in candidates {
+        let mut updated = document.clone();
+        insert(&mut updated, key, candidate)?;
+        match from_document(&updated) {
+            Ok(_) => return write_document(path, &updated).await,
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    Err(last_error.expect("at least one candidate was tried"))
+        .with_context(|| format!("Invalid value '{}' for {}", value, key))
+}
+
+/// Removes a dotted key from the config file, validating the resulting config before writing it.
+pub async fn unset(path: &Path, key: &str) -> Result<()> {
+    let mut document = read_document(path).await?;
+    if remove(&mut document, key).is_none() {
+        anyhow::bail!("{} is not set.", key);
+    }
+    from_document(&document).with_context(|| format!("Unable to unset {}", key))?;
+    write_document(path, &document).await
+}
+
+/// Returns every key set in the config file as `(dotted key, value)` pairs.
+pub async fn list(path: &Path) -> Result<Vec<(String, toml::Value)>> {
+    // Load the typed config first so an invalid file is reported rather than listed.
+    Config::load(path).await?;
+    let table = read_table(path).await?;
+
+    let mut entries = Vec::new();
+    flatten("", table, &mut entries);
+    Ok(entries)
+}
+
+fn flatten(prefix: &str, table: toml::Table, entries: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            toml::Value::Table(table) => flatten(&key, table, entries),
+            value => entries.push((key, value)),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> Option<Value> {
+    let mut document: DocumentMut = format!("value = {}", value).parse().ok()?;
+    let mut value = document.remove("value")?.into_value().ok()?;
+    value.decor_mut().clear();
+    Some(value)
+}
+
+/// Splits a dotted key into the names of the tables it is in and its own name.
+fn split_key(key: &str) -> (impl Iterator<Item = &str>, &str) {
+    let (parents, leaf) = match key.rsplit_once('.') {
+        Some((parents, leaf)) => (Some(parents), leaf),
+        None => (None, key),
+    };
+    (
+        parents.into_iter().flat_map(|parents| parents.split('.')),
+        leaf,
+    )
+}
+
+fn insert(document: &mut DocumentMut, key: &str, value: Value) -> Result<()> {
+    let (parents, leaf) = split_key(key);
+    let mut current: &mut dyn TableLike = document.as_table_mut();
+    for part in parents {
+        current = current
+            .entry(part)
+            .or_insert_with(|| {
+                // Only the tables that end up holding keys get a `[header]`.
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .with_context(|| format!("{} is not a table", part))?;
+    }
+
+    // Keep any comment after the old value.
+    match current.get_mut(leaf).and_then(Item::as_value_mut) {
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = value;
+            *old.decor_mut() = decor;
+        }
+        None => {
+            current.insert(leaf, Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+/// Removes a dotted key, returning its value if it was set. Unlike `insert`, this never
+/// creates tables.
+fn remove(document: &mut DocumentMut, key: &str) -> Option<Item> {
+    let (parents, leaf) = split_key(key);
+    let mut current: &mut dyn TableLike = document.as_table_mut();
+    for part in parents {
+        current = current.get_mut(part)?.as_table_like_mut()?;
+    }
+
+    // The comments right above a key go with it, but those before a blank line, like the one
+    // at the top of the file, are moved to the next key.
+    let kept = current.get_key_value(leaf).and_then(|(key, _)| {
+        let prefix = key.leaf_decor().prefix()?.as_str()?;
+        prefix
+            .rfind("\n\n")
+            .map(|end| prefix[..end + 2].to_string())
+    });
+    let next = current
+        .iter()
+        .skip_while(|(key, _)| *key != leaf)
+        .skip(1)
+        .find(|(_, item)| item.is_value())
+        .map(|(key, _)| key.to_string());
+
+    let removed = current.remove(leaf)?;
+    if let (Some(kept), Some(next)) = (kept, next) {
+        let mut key = current.key_mut(&next).expect("the next key is still there");
+        let prefix = key.leaf_decor().prefix().and_then(|prefix| prefix.as_str());
+        let prefix = format!("{}{}", kept, prefix.unwrap_or_default());
+        key.leaf_decor_mut().set_prefix(prefix);
+    }
+    Some(removed)
+}
+
+/// Formats a value for display, printing strings without quotes.
+pub fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"# My howto settings
+
+# The work account
+provider = "openai"   # work account
+model = "gpt-4o"
+
+[safety]
+# Be careful
+policy = "warn"  # for now
+"#;
+
+    fn document() -> DocumentMut {
+        DOCUMENT.parse().unwrap()
+    }
+
+    fn set(document: &mut DocumentMut, key: &str, value: &str) {
+        insert(document, key, parse_value(value).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn sets_new_keys() {
+        let mut document = document();
+        set(&mut document, "temperature", "0.2");
+        set(&mut document, "profiles.work.model", r#""llama3""#);
+
+        assert_eq!(
+            document.to_string(),
+            r#"# My howto settings
+
+# The work account
+provider = "openai"   # work account
+model = "gpt-4o"
+temperature = 0.2
+
+[safety]
+# Be careful
+policy = "warn"  # for now
+
+[profiles.work]
+model = "llama3"
+"#
+        );
+    }
+
+    #[test]
+    fn overwrites_keys_keeping_their_comments() {
+        let mut document = document();
+        set(&mut document, "provider", r#""anthropic""#);
+        set(&mut document, "safety.policy", r#""block""#);
+
+        assert_eq!(
+            document.to_string(),
+            DOCUMENT
+                .replace(r#""openai""#, r#""anthropic""#)
+                .replace(r#""warn""#, r#""block""#)
+        );
+    }
+
+    #[test]
+    fn unsets_the_first_key_keeping_the_file_header() {
+        let mut document = document();
+        assert!(remove(&mut document, "provider").is_some());
+
+        assert_eq!(
+            document.to_string(),
+            r#"# My howto settings
+
+model = "gpt-4o"
+
+[safety]
+# Be careful
+policy = "warn"  # for now
+"#
+        );
+    }
+
+    #[test]
+    fn unsets_keys_in_tables() {
+        let mut document = document();
+        assert!(remove(&mut document, "safety.policy").is_some());
+
+        assert_eq!(
+            document.to_string(),
+            r#"# My howto settings
+
+# The work account
+provider = "openai"   # work account
+model = "gpt-4o"
+
+[safety]
+"#
+        );
+    }
+
+    #[test]
+    fn unsets_missing_keys_without_creating_tables() {
+        let mut document = document();
+        assert!(remove(&mut document, "nope.deep.key").is_none());
+        assert!(remove(&mut document, "safety.nope").is_none());
+        assert!(remove(&mut document, "model.nope").is_none());
+        assert_eq!(document.to_string(), DOCUMENT);
+    }
+
+    #[test]
+    fn refuses_to_set_keys_inside_values() {
+        let mut document = document();
+        assert!(insert(&mut document, "model.nope", parse_value("1").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+
+/// Keys already fetched, by command, so a helper that prompts or unlocks a vault runs once.
+static CACHE: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+/// Runs an `api_key_command` and returns what it prints, like a git credential helper.
+///
+/// The helper's stderr and stdin are the terminal's, so it can ask for a master password.
+pub fn api_key_from_command(command: &str) -> Result<String> {
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(key) = cache.lock().expect("cache lock").get(command) {
+        return Ok(key.clone());
+    }
+
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let output = Command::new(shell)
+        .args([flag, command])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Unable to run api_key_command `{}`", command))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "api_key_command `{}` failed with {}. Any output from it is above.",
+            command,
+            output.status
+        );
+    }
+
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("api_key_command `{}` printed invalid UTF-8", command))?;
+    // Password managers often print more than the secret, e.g. `pass` puts metadata on later lines.
+    let key = stdout.lines().next().unwrap_or_default().trim().to_string();
+    if key.is_empty() {
+        anyhow::bail!("api_key_command `{}` did not print an API key.", command);
+    }
+
+    cache
+        .lock()
+        .expect("cache lock")
+        .insert(command.to_string(), key.clone());
+    Ok(key)
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, ErrorKind, IsTerminal};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::run;
+
+/// The name of the encrypted credentials file in the data dir.
+pub const FILE_NAME: &str = "credentials.enc";
+
+/// The environment variable holding the passphrase, for when there is no terminal to ask on.
+pub const PASSPHRASE_ENV_VAR: &str = "HOWTO_CLI_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+/// OWASP's recommendation for PBKDF2-HMAC-SHA256.
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// API keys encrypted with a key derived from a passphrase, for machines without a keyring.
+///
+/// Account names are stored in the clear so lookups can tell whether a key is there without
+/// asking for the passphrase. Each key is bound to its account, so keys cannot be swapped.
+pub struct EncryptedFile {
+    path: PathBuf,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Contents {
+    /// Hex encoded.
+    salt: String,
+    entries: BTreeMap<String, Sealed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    /// Hex encoded.
+    nonce: String,
+    /// Hex encoded, with the authentication tag appended.
+    ciphertext: String,
+}
+
+impl EncryptedFile {
+    pub fn new(path: PathBuf) -> EncryptedFile {
+        EncryptedFile { path }
+    }
+
+    pub fn contains(&self, account: &str) -> Result<bool> {
+        Ok(self
+            .read()?
+            .is_some_and(|contents| contents.entries.contains_key(account)))
+    }
+
+    pub fn get(&self, account: &str) -> Result<Option<String>> {
+        let Some(contents) = self.read()? else {
+            return Ok(None);
+        };
+        let Some(sealed) = contents.entries.get(account) else {
+            return Ok(None);
+        };
+
+        let key = derive_key(&read_passphrase(false)?, &decode(&contents.salt)?);
+        open(&key, account, sealed).map(Some)
+    }
+
+    pub fn set(&self, account: &str, api_key: &str) -> Result<()> {
+        let (mut contents, creating) = match self.read()? {
+            Some(contents) => (contents, false),
+            None => {
+                let mut salt = [0; SALT_LEN];
+                fill_random(&mut salt)?;
+                let contents = Contents {
+                    salt: encode(&salt),
+                    entries: BTreeMap::new(),
+                };
+                (contents, true)
+            }
+        };
+
+        let key = derive_key(&read_passphrase(creating)?, &decode(&contents.salt)?);
+        // Every entry must share a passphrase, so check it against one that is already there.
+        if let Some((other, sealed)) = contents.entries.iter().next() {
+            open(&key, other, sealed)?;
+        }
+
+        contents
+            .entries
+            .insert(account.to_string(), seal(&key, account, api_key)?);
+        self.write(&contents)
+    }
+
+    /// Removes a key, returning whether there was one. No passphrase is needed.
+    pub fn delete(&self, account: &str) -> Result<bool> {
+        let Some(mut contents) = self.read()? else {
+            return Ok(false);
+        };
+        if contents.entries.remove(account).is_none() {
+            return Ok(false);
+        }
+        self.write(&contents)?;
+        Ok(true)
+    }
+
+    fn read(&self) -> Result<Option<Contents>> {
+        let bytes = match std::fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Unable to read {}", self.path.display()))
+            }
+        };
+
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .with_context(|| format!("Unable to parse {}", self.path.display()))
+    }
+
+    fn write(&self, contents: &Contents) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Unable to create {}", parent.display()))?;
+        }
+
+        let json = serde_json::to_vec_pretty(contents)?;
+        std::fs::write(&self.path, json)
+            .with_context(|| format!("Unable to write {}", self.path.display()))?;
+        super::restrict_permissions(&self.path)
+    }
+}
+
+/// Reads the passphrase from the environment, or asks for it on the terminal.
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VAR) {
+        return Ok(passphrase);
+    }
+    if !io::stdin().is_terminal() {
+        anyhow::bail!(
+            "The encrypted credentials file needs a passphrase. Set {} or run howto in a terminal.",
+            PASSPHRASE_ENV_VAR
+        );
+    }
+
+    let passphrase = run::read_secret("Passphrase for the credentials file: ")?.unwrap_or_default();
+    if passphrase.is_empty() {
+        anyhow::bail!("The passphrase cannot be empty.");
+    }
+    if confirm && run::read_secret("Repeat the passphrase: ")?.as_deref() != Some(&passphrase) {
+        anyhow::bail!("The passphrases do not match.");
+    }
+    Ok(passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).expect("iterations are not zero"),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("key has the right length"))
+}
+
+fn seal(key: &LessSafeKey, account: &str, api_key: &str) -> Result<Sealed> {
+    let mut nonce = [0; NONCE_LEN];
+    fill_random(&mut nonce)?;
+
+    let mut ciphertext = api_key.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(account.as_bytes()),
+        &mut ciphertext,
+    )
+    .map_err(|_| anyhow::anyhow!("Unable to encrypt the API key"))?;
+
+    Ok(Sealed {
+        nonce: encode(&nonce),
+        ciphertext: encode(&ciphertext),
+    })
+}
+
+fn open(key: &LessSafeKey, account: &str, sealed: &Sealed) -> Result<String> {
+    let nonce = <[u8; NONCE_LEN]>::try_from(decode(&sealed.nonce)?)
+        .map_err(|_| anyhow::anyhow!("The credentials file is corrupt"))?;
+
+    let mut ciphertext = decode(&sealed.ciphertext)?;
+    let plaintext = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(account.as_bytes()),
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase for the credentials file"))?;
+
+    String::from_utf8(plaintext.to_vec()).context("The credentials file is corrupt")
+}
+
+fn fill_random(bytes: &mut [u8]) -> Result<()> {
+    SystemRandom::new()
+        .fill(bytes)
+        .map_err(|_| anyhow::anyhow!("Unable to generate random bytes"))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode(hex: &str) -> Result<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .context("The credentials file is corrupt")
+        })
+        .collect()
+}
@@ -0,0 +1,148 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::environment::is_on_path;
+
+/// The service name keys are stored under.
+const SERVICE: &str = "howto-cli";
+
+/// `security` exits with this when the Keychain has no matching item.
+const MACOS_NOT_FOUND: i32 = 44;
+
+// The keyring is reached through its command line tools, as there is no portable API for it:
+// `secret-tool` for the Secret Service on Linux and `security` for the macOS Keychain.
+
+/// Whether a keyring can be used. The Secret Service needs a D-Bus session, which headless
+/// machines usually lack.
+pub fn is_available() -> bool {
+    if cfg!(target_os = "macos") {
+        is_on_path("security")
+    } else {
+        env::var_os("DBUS_SESSION_BUS_ADDRESS").is_some() && is_on_path("secret-tool")
+    }
+}
+
+pub fn get(account: &str) -> Result<Option<String>> {
+    let output = if cfg!(target_os = "macos") {
+        run(
+            "security",
+            &["find-generic-password", "-s", SERVICE, "-a", account, "-w"],
+            None,
+        )?
+    } else {
+        run(
+            "secret-tool",
+            &["lookup", "service", SERVICE, "account", account],
+            None,
+        )?
+    };
+
+    if output.status.success() {
+        let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Ok((!key.is_empty()).then_some(key));
+    }
+    if is_not_found(&output) {
+        return Ok(None);
+    }
+    Err(failure("read from the keyring", &output))
+}
+
+pub fn set(account: &str, key: &str) -> Result<()> {
+    let output = if cfg!(target_os = "macos") {
+        // A trailing `-w` without a value makes `security` ask for the password, and it asks
+        // twice. Answering on stdin keeps the key out of the argument list, which `ps` shows to
+        // every user.
+        let answers = format!("{}\n{}\n", key, key);
+        run(
+            "security",
+            &[
+                "add-generic-password",
+                "-U",
+                "-s",
+                SERVICE,
+                "-a",
+                account,
+                "-w",
+            ],
+            Some(&answers),
+        )?
+    } else {
+        let label = format!("howto API key ({})", account);
+        run(
+            "secret-tool",
+            &[
+                "store", "--label", &label, "service", SERVICE, "account", account,
+            ],
+            Some(key),
+        )?
+    };
+
+    if !output.status.success() {
+        return Err(failure("write to the keyring", &output));
+    }
+    Ok(())
+}
+
+/// Removes a key, returning whether there was one.
+pub fn delete(account: &str) -> Result<bool> {
+    if get(account)?.is_none() {
+        return Ok(false);
+    }
+
+    let output = if cfg!(target_os = "macos") {
+        run(
+            "security",
+            &["delete-generic-password", "-s", SERVICE, "-a", account],
+            None,
+        )?
+    } else {
+        run(
+            "secret-tool",
+            &["clear", "service", SERVICE, "account", account],
+            None,
+        )?
+    };
+
+    if !output.status.success() {
+        return Err(failure("remove from the keyring", &output));
+    }
+    Ok(true)
+}
+
+fn run(program: &str, args: &[&str], stdin: Option<&str>) -> Result<Output> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Unable to run {}", program))?;
+
+    let mut pipe = child.stdin.take().expect("stdin is piped");
+    if let Some(stdin) = stdin {
+        pipe.write_all(stdin.as_bytes())
+            .with_context(|| format!("Unable to write to {}", program))?;
+    }
+    drop(pipe);
+
+    child
+        .wait_with_output()
+        .with_context(|| format!("Unable to run {}", program))
+}
+
+/// `secret-tool` exits with 1 and says nothing when there is no matching secret.
+fn is_not_found(output: &Output) -> bool {
+    if cfg!(target_os = "macos") {
+        output.status.code() == Some(MACOS_NOT_FOUND)
+    } else {
+        output.stderr.is_empty()
+    }
+}
+
+fn failure(action: &str, output: &Output) -> anyhow::Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    anyhow::anyhow!("Unable to {}: {}", action, stderr.trim())
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::provider::ProviderKind;
+
+mod command;
+mod file;
+mod keyring;
+
+pub use command::api_key_from_command;
+use file::EncryptedFile;
+
+/// The profile used when none is given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Where `howto auth login` keeps an API key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Store {
+    /// The OS keyring: the Secret Service on Linux or the Keychain on macOS.
+    Keyring,
+    /// A file in the data dir, encrypted with a passphrase.
+    File,
+}
+
+impl Store {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Store::Keyring => "the keyring",
+            Store::File => "the encrypted credentials file",
+        }
+    }
+}
+
+/// API keys saved with `howto auth login`, stored per profile and provider.
+pub struct Credentials {
+    file: EncryptedFile,
+}
+
+impl Credentials {
+    pub fn new(data_dir: &Path) -> Credentials {
+        Credentials {
+            file: EncryptedFile::new(data_dir.join(file::FILE_NAME)),
+        }
+    }
+
+    /// Looks up a key, trying the keyring before the encrypted file.
+    pub fn get(&self, profile: &str, kind: ProviderKind) -> Result<Option<String>> {
+        let account = account(profile, kind);
+
+        if let Some(key) = keyring_get(&account) {
+            return Ok(Some(key));
+        }
+        self.file.get(&account)
+    }
+
+    /// Where a key is stored, without reading it from the encrypted file.
+    pub fn locate(&self, profile: &str, kind: ProviderKind) -> Result<Option<Store>> {
+        let account = account(profile, kind);
+
+        if keyring_get(&account).is_some() {
+            return Ok(Some(Store::Keyring));
+        }
+        Ok(self.file.contains(&account)?.then_some(Store::File))
+    }
+
+    /// Saves a key, in the keyring if there is one. Returns where it went.
+    pub fn set(
+        &self,
+        profile: &str,
+        kind: ProviderKind,
+        key: &str,
+        store: Option<Store>,
+    ) -> Result<Store> {
+        let account = account(profile, kind);
+        let store = store.unwrap_or(if keyring::is_available() {
+            Store::Keyring
+        } else {
+            Store::File
+        });
+
+        match store {
+            Store::Keyring => {
+                if !keyring::is_available() {
+                    anyhow::bail!(
+                        "No keyring is available. Install secret-tool and run howto in a desktop session, or use --store file."
+                    );
+                }
+                keyring::set(&account, key)?;
+            }
+            Store::File => self.file.set(&account, key)?,
+        }
+        Ok(store)
+    }
+
+    /// Removes a key from every store, returning those it was in.
+    pub fn delete(&self, profile: &str, kind: ProviderKind) -> Result<Vec<Store>> {
+        let account = account(profile, kind);
+        let mut removed = Vec::new();
+
+        if keyring::is_available() {
+            match keyring::delete(&account) {
+                Ok(true) => removed.push(Store::Keyring),
+                Ok(false) => {}
+                Err(err) => warn_keyring(&err),
+            }
+        }
+        if self.file.delete(&account)? {
+            removed.push(Store::File);
+        }
+        Ok(removed)
+    }
+}
+
+/// Reads a key from the keyring. A keyring that fails, e.g. because no Secret Service is running,
+/// is treated as empty so that the other stores are still checked.
+fn keyring_get(account: &str) -> Option<String> {
+    if !keyring::is_available() {
+        return None;
+    }
+    keyring::get(account).unwrap_or_else(|err| {
+        warn_keyring(&err);
+        None
+    })
+}
+
+/// Warns that the keyring failed, once, as it usually fails the same way for every key.
+fn warn_keyring(err: &anyhow::Error) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!("Warning: {:#}. Skipping the keyring.", err);
+    }
+}
+
+/// The name a key is stored under.
+fn account(profile: &str, kind: ProviderKind) -> String {
+    format!("{}/{}", profile, kind.name())
+}
+
+/// Warns on stderr if a file holding a plaintext key can be read by other users.
+#[cfg(unix)]
+pub fn check_permissions(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return;
+    };
+    if metadata.permissions().mode() & 0o077 != 0 {
+        eprintln!(
+            "Warning: {} can be read by other users. Run `chmod 600 {}`, or move the key to the keyring with `howto auth login`.",
+            path.display(),
+            path.display()
+        );
+    }
+}
+
+#[cfg(not(unix))]
+pub fn check_permissions(_path: &Path) {}
+
+/// Makes a file readable and writable by its owner only.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    use anyhow::Context;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Unable to set permissions on {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
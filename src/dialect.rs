@@ -0,0 +1,129 @@
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use clap::ValueEnum;
+
+/// The shell language a generated command should be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Dialect {
+    /// POSIX sh, also used for dash, ksh and unknown shells.
+    Sh,
+    Bash,
+    Zsh,
+    Fish,
+    #[value(name = "powershell", alias = "pwsh")]
+    PowerShell,
+    #[value(name = "nushell", alias = "nu")]
+    Nushell,
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Sh => write!(f, "POSIX sh"),
+            Dialect::Bash => write!(f, "bash"),
+            Dialect::Zsh => write!(f, "zsh"),
+            Dialect::Fish => write!(f, "fish"),
+            Dialect::PowerShell => write!(f, "PowerShell"),
+            Dialect::Nushell => write!(f, "nushell"),
+        }
+    }
+}
+
+impl Dialect {
+    /// Works out the dialect from a shell path such as `/usr/bin/fish`.
+    pub fn detect(shell: &str) -> Dialect {
+        let name = Path::new(shell)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or(shell);
+
+        match name {
+            "bash" => Dialect::Bash,
+            "zsh" => Dialect::Zsh,
+            "fish" => Dialect::Fish,
+            "pwsh" | "powershell" => Dialect::PowerShell,
+            "nu" => Dialect::Nushell,
+            _ => Dialect::Sh,
+        }
+    }
+
+    /// The program that runs commands in this dialect.
+    pub fn program(self) -> &'static str {
+        match self {
+            Dialect::Sh => "sh",
+            Dialect::Bash => "bash",
+            Dialect::Zsh => "zsh",
+            Dialect::Fish => "fish",
+            Dialect::PowerShell => "pwsh",
+            Dialect::Nushell => "nu",
+        }
+    }
+
+    /// Instructions appended to the system message so the model writes in this dialect.
+    pub fn instructions(self) -> String {
+        let hints = match self {
+            Dialect::Sh => "Only use POSIX sh features; avoid bashisms such as [[ ]], arrays and brace expansion.",
+            Dialect::Bash => "Bash features such as [[ ]], arrays and process substitution are available.",
+            Dialect::Zsh => "Zsh features such as extended globbing (e.g. **/*.rs) are available.",
+            Dialect::Fish => "Use fish syntax: `set VAR value` instead of `VAR=value`, `(cmd)` instead of backticks, `and`/`or` or `&&`/`||` for chaining, and `env VAR=value cmd` for one-off variables.",
+            Dialect::PowerShell => "Use PowerShell syntax and cmdlets such as Get-ChildItem, Where-Object and Select-String; `$env:VAR` for environment variables.",
+            Dialect::Nushell => "Use nushell syntax: pipelines of structured data with commands such as `ls`, `where`, `sort-by` and `get`; `$env.VAR` for environment variables; `;` rather than `&&` for sequencing.",
+        };
+
+        format!(
+            "The command will be run by {}, so it must be valid {} syntax. {}",
+            self.program(),
+            self,
+            hints
+        )
+    }
+
+    /// Asks the dialect's own shell to parse the command without running it.
+    ///
+    /// Returns `None` if the shell is not installed, otherwise the parse error, if any.
+    pub fn check_syntax(self, command: &str) -> Option<Result<(), String>> {
+        const COMMAND_ENV_VAR: &str = "HOWTO_CLI_SYNTAX_CHECK";
+
+        let mut check = Command::new(self.program());
+        match self {
+            Dialect::Sh | Dialect::Bash | Dialect::Zsh => check.args(["-n", "-c", command]),
+            Dialect::Fish => check.args(["--no-execute", "-c", command]),
+            // PowerShell and nushell have no "parse only" flag, so hand the command to their parsers.
+            Dialect::PowerShell => check
+                .args([
+                    "-NoProfile",
+                    "-NonInteractive",
+                    "-Command",
+                    "$errors = $null; \
+                     [void][System.Management.Automation.Language.Parser]::ParseInput(\
+                     $env:HOWTO_CLI_SYNTAX_CHECK, [ref]$null, [ref]$errors); \
+                     if ($errors) { $errors | ForEach-Object { $_.Message }; exit 1 }",
+                ])
+                .env(COMMAND_ENV_VAR, command),
+            Dialect::Nushell => check
+                .args([
+                    "--no-config-file",
+                    "-c",
+                    "if not ($env.HOWTO_CLI_SYNTAX_CHECK | nu-check) { exit 1 }",
+                ])
+                .env(COMMAND_ENV_VAR, command),
+        };
+
+        let output = check.output().ok()?;
+        if output.status.success() {
+            return Some(Ok(()));
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let message = if stderr.trim().is_empty() {
+            stdout
+        } else {
+            stderr
+        };
+
+        Some(Err(message.trim().to_string()))
+    }
+}
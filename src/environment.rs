@@ -0,0 +1,163 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Binaries worth telling the model about, either because they are better alternatives to the
+/// classics or because their presence decides which of several commands will work.
+const TOOLS: &[&str] = &[
+    // Package managers
+    "apt",
+    "dnf",
+    "yum",
+    "pacman",
+    "zypper",
+    "apk",
+    "brew",
+    "port",
+    "nix",
+    // Containers and services
+    "docker",
+    "podman",
+    "kubectl",
+    "systemctl",
+    "launchctl",
+    // Modern alternatives to the classics
+    "rg",
+    "fd",
+    "fdfind",
+    "bat",
+    "eza",
+    "exa",
+    "fzf",
+    "sd",
+    "dust",
+    "duf",
+    "htop",
+    "btop",
+    // Data wrangling
+    "jq",
+    "yq",
+    "xsv",
+    "gawk",
+    "gsed",
+    "python3",
+    "perl",
+    // Networking
+    "curl",
+    "wget",
+    "ss",
+    "netstat",
+    "lsof",
+    "ip",
+    "ifconfig",
+    "nc",
+    // Archives
+    "zstd",
+    "7z",
+    "unzip",
+    "pigz",
+    // Version control
+    "git",
+];
+
+/// A description of the machine the command will run on.
+pub struct Environment {
+    pub os: String,
+    pub arch: String,
+    pub kernel: Option<String>,
+    pub distribution: Option<String>,
+    pub shell: String,
+    pub tools: Vec<&'static str>,
+}
+
+impl Environment {
+    /// Gathers details about the local machine. Anything that cannot be determined is left out.
+    pub fn detect(shell: &str) -> Environment {
+        Environment {
+            os: env::consts::OS.to_string(),
+            arch: env::consts::ARCH.to_string(),
+            kernel: command_output("uname", &["-sr"]),
+            distribution: detect_distribution(),
+            shell: shell.to_string(),
+            tools: TOOLS
+                .iter()
+                .copied()
+                .filter(|tool| is_on_path(tool))
+                .collect(),
+        }
+    }
+
+    /// Formats the environment as an `<environment>` block for the user message.
+    pub fn to_prompt(&self) -> String {
+        let mut lines = vec![format!("os: {} ({})", self.os, self.arch)];
+
+        if let Some(kernel) = &self.kernel {
+            lines.push(format!("kernel: {}", kernel));
+        }
+        if let Some(distribution) = &self.distribution {
+            lines.push(format!("distribution: {}", distribution));
+        }
+        lines.push(format!("shell: {}", self.shell));
+        if !self.tools.is_empty() {
+            lines.push(format!("installed tools: {}", self.tools.join(", ")));
+        }
+
+        format!("<environment>\n{}\n</environment>", lines.join("\n"))
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let stdout = stdout.trim();
+    (!stdout.is_empty()).then(|| stdout.to_string())
+}
+
+fn detect_distribution() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        return command_output("sw_vers", &["-productVersion"])
+            .map(|version| format!("macOS {}", version));
+    }
+
+    let os_release = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+
+    let field = |name: &str| {
+        os_release.lines().find_map(|line| {
+            line.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+                .map(|value| value.trim_matches('"').to_string())
+        })
+    };
+
+    field("PRETTY_NAME").or_else(|| field("NAME"))
+}
+
+/// Whether an executable with this name is in one of the `$PATH` directories.
+pub fn is_on_path(program: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+
+    env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file() || path.with_extension("exe").is_file()
+}
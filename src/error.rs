@@ -0,0 +1,185 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Failures that callers may want to tell apart, each with a stable code and exit code.
+///
+/// Anything else is reported with the code `error` and exits with 1.
+#[derive(Debug)]
+pub enum Error {
+    /// No API key could be found for a provider that needs one.
+    MissingCredentials {
+        provider: String,
+        profile: String,
+        env_var: String,
+    },
+    /// The provider rejected the API key.
+    AuthFailed { provider: String, message: String },
+    /// The provider is limiting the rate of requests.
+    RateLimited {
+        provider: String,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// The provider failed with a server error, or is overloaded.
+    Unavailable {
+        provider: String,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// The provider could not be reached.
+    Network { message: String },
+    /// The provider did not respond in time. `setting` is the flag that controls how long to wait.
+    Timeout { setting: &'static str },
+    /// The model says the action cannot be done with a command.
+    NoCommand,
+    /// The model declined to help with the action.
+    Refused { message: String },
+    /// The model's response could not be understood.
+    Unparseable { response: String },
+    /// The generated command did not parse in the target shell, even after a correction.
+    InvalidSyntax {
+        dialect: String,
+        command: String,
+        message: String,
+    },
+    /// `--run` refused a high risk command.
+    UnsafeCommand,
+    /// The user declined to pick or run a command.
+    Cancelled,
+}
+
+impl Error {
+    /// The identifier used for this error in JSON output. These never change.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::MissingCredentials { .. } => "missing_credentials",
+            Error::AuthFailed { .. } => "auth_failed",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::Unavailable { .. } => "unavailable",
+            Error::Network { .. } => "network",
+            Error::Timeout { .. } => "timeout",
+            Error::NoCommand => "no_command",
+            Error::Refused { .. } => "refused",
+            Error::Unparseable { .. } => "unparseable",
+            Error::InvalidSyntax { .. } => "invalid_syntax",
+            Error::UnsafeCommand => "unsafe_command",
+            Error::Cancelled => "cancelled",
+        }
+    }
+
+    /// The process exit code for this error. These never change; see the README.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::MissingCredentials { .. } => 3,
+            Error::AuthFailed { .. } => 4,
+            Error::RateLimited { .. } => 5,
+            Error::Network { .. } => 6,
+            Error::Timeout { .. } => 7,
+            Error::NoCommand => 8,
+            Error::Refused { .. } => 9,
+            Error::Unparseable { .. } => 10,
+            Error::InvalidSyntax { .. } => 11,
+            Error::UnsafeCommand => 12,
+            Error::Unavailable { .. } => 13,
+            // The conventional code for a process interrupted by the user.
+            Error::Cancelled => 130,
+        }
+    }
+
+    /// Whether the same request may succeed if it is sent again.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::RateLimited { .. }
+                | Error::Unavailable { .. }
+                | Error::Network { .. }
+                | Error::Timeout { .. }
+        )
+    }
+
+    /// Whether the provider could not be reached at all, as opposed to turning the request down.
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, Error::Network { .. } | Error::Timeout { .. })
+    }
+
+    /// How long the provider asked us to wait before trying again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_after, .. } | Error::Unavailable { retry_after, .. } => {
+                *retry_after
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingCredentials {
+                provider,
+                profile,
+                env_var,
+            } => write!(
+                f,
+                "No {} API key found for profile {}. Run `howto auth login --provider {} --profile {}`, or set {}.",
+                provider, profile, provider, profile, env_var
+            ),
+            Error::AuthFailed { provider, message } => {
+                write!(f, "{} rejected the API key: {}", provider, message)
+            }
+            Error::RateLimited {
+                provider, message, ..
+            } => write!(f, "{} is rate limiting requests: {}", provider, message),
+            Error::Unavailable {
+                provider, message, ..
+            } => write!(f, "{} is unavailable: {}", provider, message),
+            Error::Network { message } => write!(f, "Unable to reach the provider: {}", message),
+            Error::Timeout { setting } => {
+                write!(f, "Timed out. Raise {} to wait longer.", setting)
+            }
+            Error::NoCommand => write!(f, "The action cannot be accomplished with a command."),
+            Error::Refused { message } => write!(f, "The model refused the action: {}", message),
+            Error::Unparseable { response } => write!(
+                f,
+                "The model's response did not contain a command.\n\n{}",
+                response
+            ),
+            Error::InvalidSyntax {
+                dialect,
+                command,
+                message,
+            } => write!(
+                f,
+                "The generated command is not valid {} syntax.\n\n  {}\n\n{}",
+                dialect, command, message
+            ),
+            Error::UnsafeCommand => write!(
+                f,
+                "Refusing to run a high-risk command. Pass --force to run it anyway."
+            ),
+            Error::Cancelled => write!(f, "Cancelled."),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The typed error behind any error, looking through context added on top of it.
+pub fn find(err: &anyhow::Error) -> Option<&Error> {
+    err.chain().find_map(|cause| cause.downcast_ref::<Error>())
+}
+
+/// The code for any error.
+pub fn code(err: &anyhow::Error) -> &'static str {
+    find(err).map_or("error", Error::code)
+}
+
+/// The exit code for any error.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    find(err).map_or(1, Error::exit_code)
+}
+
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    matches!(find(err), Some(Error::Cancelled))
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+/// A breakdown of what a command does, token by token.
+#[derive(Serialize, Deserialize)]
+pub struct Explanation {
+    pub summary: String,
+    pub parts: Vec<Part>,
+}
+
+/// One program, argument, flag or operator in a command.
+#[derive(Serialize, Deserialize)]
+pub struct Part {
+    pub token: String,
+    pub meaning: String,
+}
+
+/// Tokens longer than this are printed on their own line rather than widening the table.
+const MAX_TOKEN_WIDTH: usize = 32;
+
+impl Explanation {
+    /// Parses the `<explanation>` block from a model response.
+    pub fn parse(content: &str) -> Option<Explanation> {
+        let explanation = tag_contents(content, "explanation")?;
+
+        let summary = tag_contents(explanation, "summary")
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        let mut parts = Vec::new();
+        let mut rest = explanation;
+        while let Some(part) = tag_contents(rest, "part") {
+            if let (Some(token), Some(meaning)) =
+                (tag_contents(part, "token"), tag_contents(part, "meaning"))
+            {
+                parts.push(Part {
+                    token: token.trim().to_string(),
+                    meaning: meaning.trim().to_string(),
+                });
+            }
+
+            let consumed = rest.find("</part>").expect("part was closed") + "</part>".len();
+            rest = &rest[consumed..];
+        }
+
+        if summary.is_empty() && parts.is_empty() {
+            return None;
+        }
+
+        Some(Explanation { summary, parts })
+    }
+
+    /// Formats the explanation as a summary followed by an aligned table of tokens.
+    pub fn render(&self) -> String {
+        let width = self
+            .parts
+            .iter()
+            .map(|part| part.token.chars().count())
+            .filter(|&width| width <= MAX_TOKEN_WIDTH)
+            .max()
+            .unwrap_or(0);
+
+        let mut lines = Vec::new();
+        if !self.summary.is_empty() {
+            lines.push(self.summary.clone());
+            lines.push(String::new());
+        }
+
+        for part in &self.parts {
+            if part.token.chars().count() > width {
+                lines.push(format!("  {}", part.token));
+                lines.push(format!("  {:width$}  {}", "", part.meaning, width = width));
+            } else {
+                lines.push(format!(
+                    "  {:width$}  {}",
+                    part.token,
+                    part.meaning,
+                    width = width
+                ));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Returns the text between the first `<tag>` and the following `</tag>`.
+pub fn tag_contents<'a>(content: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+
+    let start = content.find(&open)? + open.len();
+    let end = content[start..].find(&close)? + start;
+    Some(&content[start..end])
+}
@@ -0,0 +1,264 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::dialect::Dialect;
+use crate::environment::Environment;
+use crate::error::Error;
+use crate::explain::Explanation;
+use crate::provider::{CommandGenerator, Completion, Message, Usage};
+use crate::response;
+
+const SYSTEM_MESSAGE: &str = r#"
+You are an expert Unix system operator. You have intimate and detailed knowledge of CLI tools, both old and new.
+
+When the user asks for a command that accomplishes a high-level action, you respond with a CLI command that accomplishes that action.
+
+Example input:
+<action>
+go to my home directory
+</action>
+
+Example output:
+<command>
+cd ~
+</command>
+
+If the action cannot be accomplished via the CLI, you must respond with:
+<no_command/>
+
+The input may also describe the user's machine in an <environment> block. When it does, tailor the command to that operating system, distribution and shell, and prefer tools listed as installed over ones that may be missing.
+
+If the input contains <explain/>, follow the <command> block with an <explanation> block. It starts with a one sentence <summary> of what the command does, followed by one <part> per program, argument, flag or operator in the order they appear, each with the exact <token> from the command and its <meaning>.
+
+Example input:
+<action>
+find rust files changed today
+</action>
+<explain/>
+
+Example output:
+<command>
+find . -name '*.rs' -mtime -1
+</command>
+<explanation>
+<summary>Lists Rust files under the current directory modified in the last 24 hours.</summary>
+<part><token>find .</token><meaning>Search the current directory recursively</meaning></part>
+<part><token>-name '*.rs'</token><meaning>Only match names ending in .rs</meaning></part>
+<part><token>-mtime -1</token><meaning>Only match files modified less than a day ago</meaning></part>
+</explanation>
+
+The conversation may continue with follow-up actions such as "now only for .rs files" or "make it recursive". Apply them to your previous command rather than starting over, and respond in the same format.
+
+When the request comes with a JSON schema for the response, reply with JSON instead of tags: put each command in "commands" with its "rationale" and "explanation", using null for blocks that were not requested, and set "no_command" to true with no commands where you would respond with <no_command/>.
+
+If the input is an <explain> block containing a command instead of an action, respond with only the <explanation> block for that command.
+
+If the input contains an <alternatives> block with a number N, respond with N genuinely different commands for the action (e.g. different tools or approaches), ranked best first. Follow each <command> block with a <rationale> block giving a short reason to choose it, and with its <explanation> block if one was requested.
+
+Example input:
+<action>
+find large files
+</action>
+<alternatives>2</alternatives>
+
+Example output:
+<command>
+find . -type f -size +100M
+</command>
+<rationale>Works everywhere find is installed.</rationale>
+<command>
+fd --type f --size +100m
+</command>
+<rationale>Faster and respects .gitignore.</rationale>
+"#;
+
+/// Builds the system message from the config's prompt overrides and the target dialect.
+pub fn system_message(config: &Config, dialect: Dialect) -> String {
+    let mut system_message = config
+        .prompt
+        .system
+        .clone()
+        .unwrap_or_else(|| SYSTEM_MESSAGE.to_string());
+
+    system_message.push('\n');
+    system_message.push_str(&dialect.instructions());
+    system_message.push('\n');
+
+    if let Some(extra) = &config.prompt.extra {
+        system_message.push('\n');
+        system_message.push_str(extra);
+        system_message.push('\n');
+    }
+
+    system_message
+}
+
+/// Everything besides the action that shapes the prompt.
+pub struct PromptContext {
+    pub system_message: String,
+    pub environment: Option<Environment>,
+    pub dialect: Dialect,
+}
+
+/// A command generated for an action.
+#[derive(Serialize, Deserialize)]
+pub struct Generated {
+    pub command: String,
+    /// Why to pick this command over the alternatives, when alternatives were requested.
+    pub rationale: Option<String>,
+    /// Present when an explanation was requested and the model provided one.
+    pub explanation: Option<Explanation>,
+}
+
+/// The commands generated for an action, and the tokens spent on them.
+pub struct Generation {
+    pub commands: Vec<Generated>,
+    /// Summed over every request, or `None` if the provider did not report usage.
+    pub usage: Option<Usage>,
+}
+
+/// Generates `alternatives` commands for an action, ranked best first.
+///
+/// `conversation` holds earlier actions and responses, so the action can refine a previous
+/// command. The action and response are added to it on success. Fewer commands than requested
+/// may be returned if the model repeats itself or some of its commands do not parse in the
+/// target dialect. If `on_delta` is given, the first response is streamed to it as it arrives.
+pub async fn generate_command(
+    generator: &dyn CommandGenerator,
+    context: &PromptContext,
+    conversation: &mut Vec<Message>,
+    action: &str,
+    explain: bool,
+    alternatives: u8,
+    on_delta: Option<&mut (dyn for<'a> FnMut(&'a str) + Send)>,
+) -> Result<Generation> {
+    let mut user_message = format!("<action>\n{}\n</action>", action.trim());
+    // The environment only needs describing once per conversation.
+    if let Some(environment) = context
+        .environment
+        .as_ref()
+        .filter(|_| conversation.is_empty())
+    {
+        user_message = format!("{}\n{}", environment.to_prompt(), user_message);
+    }
+    if explain {
+        user_message.push_str("\n<explain/>");
+    }
+    if alternatives > 1 {
+        user_message.push_str(&format!("\n<alternatives>{}</alternatives>", alternatives));
+    }
+
+    let mut messages = vec![Message::system(context.system_message.as_str())];
+    messages.extend(conversation.iter().cloned());
+    messages.push(Message::user(user_message.as_str()));
+
+    let completion = complete(generator, &messages, on_delta).await?;
+    let (valid, errors) = check_syntax(
+        context.dialect,
+        response::parse(&completion.content, explain)?,
+    );
+
+    // If the dialect's shell is installed and rejects every command, give the model one chance to
+    // fix them before giving up.
+    if !valid.is_empty() {
+        conversation.push(Message::user(user_message));
+        conversation.push(Message::assistant(completion.content));
+        return Ok(Generation {
+            commands: valid,
+            usage: completion.usage,
+        });
+    }
+
+    let first_usage = completion.usage;
+    messages.push(Message::assistant(completion.content));
+    messages.push(Message::user(format!(
+        "{} not valid {} syntax:\n{}\nRespond with {}{}.",
+        if errors.len() > 1 {
+            "Those commands are"
+        } else {
+            "That command is"
+        },
+        context.dialect,
+        errors.join("\n"),
+        if errors.len() > 1 {
+            "corrected commands"
+        } else {
+            "a corrected command"
+        },
+        if explain { " and explanations" } else { "" }
+    )));
+
+    let completion = complete(generator, &messages, None).await?;
+    let generated = response::parse(&completion.content, explain)?;
+    let command = generated[0].command.clone();
+    let (valid, mut errors) = check_syntax(context.dialect, generated);
+
+    if valid.is_empty() {
+        return Err(Error::InvalidSyntax {
+            dialect: context.dialect.to_string(),
+            command,
+            message: errors.swap_remove(0),
+        }
+        .into());
+    }
+
+    conversation.push(Message::user(user_message));
+    conversation.push(Message::assistant(completion.content));
+    Ok(Generation {
+        commands: valid,
+        usage: Usage::sum([first_usage, completion.usage]),
+    })
+}
+
+/// Sends a request, using the provider's structured output unless the reply is being streamed,
+/// since partial JSON cannot be shown as it arrives.
+async fn complete(
+    generator: &dyn CommandGenerator,
+    messages: &[Message],
+    on_delta: Option<&mut (dyn for<'a> FnMut(&'a str) + Send)>,
+) -> Result<Completion> {
+    if let Some(on_delta) = on_delta {
+        return generator.complete_stream(messages, on_delta).await;
+    }
+
+    match generator
+        .complete_structured(messages, &response::schema())
+        .await?
+    {
+        Some(completion) => Ok(completion),
+        None => generator.complete(messages).await,
+    }
+}
+
+/// Splits commands into those that parse in the dialect and the parse errors of those that don't.
+fn check_syntax(dialect: Dialect, generated: Vec<Generated>) -> (Vec<Generated>, Vec<String>) {
+    let mut valid = Vec::new();
+    let mut errors = Vec::new();
+
+    for generated in generated {
+        match dialect.check_syntax(&generated.command) {
+            Some(Err(error)) => errors.push(error),
+            _ => valid.push(generated),
+        }
+    }
+
+    (valid, errors)
+}
+
+/// Asks the model to explain an existing command.
+pub async fn explain_command(
+    generator: &dyn CommandGenerator,
+    context: &PromptContext,
+    command: &str,
+) -> Result<Explanation> {
+    let messages = [
+        Message::system(context.system_message.as_str()),
+        Message::user(format!("<explain>\n{}\n</explain>", command.trim())),
+    ];
+
+    let completion = generator.complete(&messages).await?;
+
+    Explanation::parse(&completion.content)
+        .ok_or_else(|| anyhow::anyhow!("No explanation could be generated for the command."))
+}
@@ -0,0 +1,252 @@
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The name of the history file in the data dir.
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// How many entries are kept, unless the config file says otherwise.
+pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// A generated command and what happened to it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Numbers entries from 1 in the order they were added, for `howto !N`. Unlike positions in
+    /// the file, ids stay the same when the oldest entries are dropped. Files written before ids
+    /// existed are numbered by position when loaded.
+    #[serde(default)]
+    pub id: u64,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub action: String,
+    pub command: String,
+    pub provider: String,
+    pub model: String,
+    pub executed: bool,
+}
+
+impl Entry {
+    pub fn new(action: &str, command: &str, provider: &str, model: &str, executed: bool) -> Entry {
+        Entry {
+            id: 0,
+            timestamp: now(),
+            action: action.to_string(),
+            command: command.to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            executed,
+        }
+    }
+}
+
+/// Past queries, stored one JSON object per line, oldest first.
+pub struct History {
+    path: PathBuf,
+    max_entries: usize,
+}
+
+impl History {
+    pub fn new(path: PathBuf, max_entries: usize) -> History {
+        History { path, max_entries }
+    }
+
+    /// Reads every entry, skipping lines that cannot be parsed.
+    pub async fn load(&self) -> Result<Vec<Entry>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Unable to read {}", self.path.display()))
+            }
+        };
+
+        let mut entries = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect::<Vec<Entry>>();
+
+        let mut last_id = 0;
+        for entry in &mut entries {
+            if entry.id == 0 {
+                entry.id = last_id + 1;
+            }
+            last_id = entry.id;
+        }
+        Ok(entries)
+    }
+
+    /// Finds an entry by its id, or counting back from the latest when `number` is negative.
+    pub async fn find(&self, number: i64) -> Result<Option<Entry>> {
+        let mut entries = self.load().await?;
+        let index = match number {
+            1.. => entries
+                .iter()
+                .position(|entry| i64::try_from(entry.id) == Ok(number)),
+            ..=-1 => entries.len().checked_sub(number.unsigned_abs() as usize),
+            0 => None,
+        };
+        Ok(index.map(|index| entries.swap_remove(index)))
+    }
+
+    /// Adds an entry with the next id, dropping the oldest ones once there are more than
+    /// `max_entries`.
+    pub async fn append(&self, entry: &Entry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Unable to create {}", parent.display()))?;
+        }
+
+        let mut entries = self.load().await?;
+        let entry = Entry {
+            id: entries.last().map_or(1, |last| last.id + 1),
+            ..entry.clone()
+        };
+
+        if entries.len() < self.max_entries {
+            let mut line = serde_json::to_string(&entry)?;
+            line.push('\n');
+
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await
+                .with_context(|| format!("Unable to open {}", self.path.display()))?;
+            return file
+                .into_std()
+                .await
+                .write_all(line.as_bytes())
+                .with_context(|| format!("Unable to write {}", self.path.display()));
+        }
+
+        entries.push(entry);
+        let keep = entries.len().saturating_sub(self.max_entries);
+
+        let mut contents = String::new();
+        for entry in &entries[keep..] {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+
+        tokio::fs::write(&self.path, contents)
+            .await
+            .with_context(|| format!("Unable to write {}", self.path.display()))
+    }
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM` in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let minutes = timestamp % 86400 / 60;
+
+    // Converts days since the epoch to a civil date. See
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("howto-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    async fn commands(history: &History) -> Vec<(u64, String)> {
+        let entries = history.load().await.unwrap();
+        entries
+            .into_iter()
+            .map(|entry| (entry.id, entry.command))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn keeps_ids_when_dropping_old_entries() {
+        let path = temp_path("history-ids");
+        let _ = std::fs::remove_file(&path);
+        let history = History::new(path.clone(), 2);
+
+        for command in ["ls", "pwd", "df -h"] {
+            let entry = Entry::new("action", command, "openai", "gpt-4o", false);
+            history.append(&entry).await.unwrap();
+        }
+
+        assert_eq!(
+            commands(&history).await,
+            [(2, "pwd".to_string()), (3, "df -h".to_string())]
+        );
+        let find = |number| {
+            let history = &history;
+            async move {
+                history
+                    .find(number)
+                    .await
+                    .unwrap()
+                    .map(|entry| entry.command)
+            }
+        };
+        assert_eq!(find(1).await, None);
+        assert_eq!(find(2).await.as_deref(), Some("pwd"));
+        assert_eq!(find(3).await.as_deref(), Some("df -h"));
+        assert_eq!(find(-1).await.as_deref(), Some("df -h"));
+        assert_eq!(find(-3).await, None);
+        assert_eq!(find(0).await, None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn numbers_entries_without_ids_by_position() {
+        let path = temp_path("history-legacy");
+        let line = |command: &str| {
+            format!(
+                r#"{{"timestamp":0,"action":"a","command":"{}","provider":"openai","model":"m","executed":false}}"#,
+                command
+            )
+        };
+        std::fs::write(&path, format!("{}\n{}\n", line("ls"), line("pwd"))).unwrap();
+        let history = History::new(path.clone(), 10);
+
+        let entry = Entry::new("action", "df -h", "openai", "gpt-4o", false);
+        history.append(&entry).await.unwrap();
+        assert_eq!(
+            commands(&history).await,
+            [
+                (1, "ls".to_string()),
+                (2, "pwd".to_string()),
+                (3, "df -h".to_string())
+            ]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
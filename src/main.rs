@@ -1,140 +1,1490 @@
+use std::collections::BTreeMap;
 use std::env::{self, VarError};
-use std::path::PathBuf;
+use std::future::Future;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use async_openai::config::OpenAIConfig;
-use async_openai::types::{
-    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
-    ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
-};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
-type OpenAIClient = async_openai::Client<OpenAIConfig>;
+mod cache;
+mod clipboard;
+mod config;
+mod credentials;
+mod dialect;
+mod environment;
+mod error;
+mod explain;
+mod generate;
+mod history;
+mod provider;
+mod recipes;
+mod response;
+mod run;
+mod safety;
+mod shell_init;
+mod stream;
+
+use cache::Cache;
+use config::{Config, OfflineConfig, OutputFormat, SafetyPolicy};
+use credentials::Credentials;
+use dialect::Dialect;
+use environment::Environment;
+use explain::Explanation;
+use generate::{Generated, Generation, PromptContext};
+use history::History;
+use provider::{CommandGenerator, ProviderConfig, ProviderKind, RetryPolicy, Usage};
+use recipes::Recipes;
+use serde::Serialize;
+use stream::CommandPrinter;
 
 #[derive(Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct HowToCli {
-    #[arg(value_name = "ACTION")]
-    /// The high-level action you would like to get a CLI command for.
-    action: String,
+    #[command(subcommand)]
+    command: Option<HowToCommand>,
+
+    #[arg(value_name = "ACTION", required_unless_present = "interactive")]
+    /// The high-level action you would like to get a CLI command for, or !N to reuse history entry N.
+    action: Option<String>,
+
+    #[arg(short, long)]
+    /// Confirm the generated command, then run it in your $SHELL.
+    run: bool,
+
+    #[arg(long, requires = "run")]
+    /// Allow --run to execute commands classified as high risk.
+    force: bool,
+
+    #[arg(short, long)]
+    /// Explain each part of the generated command on stderr.
+    explain: bool,
+
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u8).range(1..=10)
+    )]
+    /// Generate up to N alternative commands and pick one from a ranked list.
+    alternatives: u8,
+
+    #[arg(long, value_enum)]
+    /// How to print the result. JSON includes the explanation, risk and token usage. Defaults to the config file, then text.
+    format: Option<OutputFormat>,
+
+    #[arg(long, conflicts_with = "run")]
+    /// Copy the command to the clipboard as well as printing it.
+    copy: bool,
+
+    #[arg(short, long, conflicts_with = "run")]
+    /// Refine commands with follow-up requests in an interactive session. Type :run to run the last one.
+    interactive: bool,
+
+    #[arg(long)]
+    /// Ignore cached commands and ask the model again. The fresh result replaces the cached one.
+    no_cache: bool,
+
+    #[arg(long)]
+    /// Ask the model even when a recipe matches the action.
+    no_recipes: bool,
+
+    #[arg(long, value_name = "NAME", global = true)]
+    /// The profile to use: a set of config file settings and saved API keys. Defaults to $HOWTO_CLI_PROFILE, then the config file, then default.
+    profile: Option<String>,
+
+    #[arg(long, value_enum, global = true)]
+    /// The shell the command should be written for. Defaults to the shell in the config file, then $SHELL.
+    shell: Option<Dialect>,
+
+    #[arg(long, value_enum, global = true)]
+    /// The model provider to use. Defaults to $HOWTO_CLI_PROVIDER, then openai.
+    provider: Option<ProviderKind>,
+
+    #[arg(long, global = true, conflicts_with_all = ["provider", "api_base"])]
+    /// Use the local model in the [offline] section of the config file, then Ollama's default, instead of the provider.
+    offline: bool,
+
+    #[arg(long, value_name = "URL", global = true)]
+    /// The base URL of the provider's API, e.g. for a gateway or local server. Defaults to $HOWTO_CLI_API_BASE, then the config file.
+    api_base: Option<String>,
+
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = parse_header, global = true)]
+    /// An extra HTTP header to send with every request. Can be repeated.
+    headers: Vec<(String, String)>,
+
+    #[arg(long, value_name = "URL", global = true)]
+    /// The proxy to send requests through. Defaults to $HOWTO_CLI_PROXY, then the config file, then $HTTPS_PROXY.
+    proxy: Option<String>,
+
+    #[arg(long, value_name = "SECONDS", global = true)]
+    /// How long to wait for each request to the provider. Defaults to $HOWTO_CLI_REQUEST_TIMEOUT, then the config file, then 60.
+    request_timeout: Option<u64>,
+
+    #[arg(long, value_name = "SECONDS", global = true)]
+    /// How long to wait for a command, including retries. Defaults to $HOWTO_CLI_TIMEOUT, then the config file, then no limit.
+    timeout: Option<u64>,
+
+    #[arg(short, long, global = true)]
+    /// The model to use. Defaults to $HOWTO_CLI_MODEL, then the config file, then the provider's default.
+    model: Option<String>,
+
+    #[arg(long, value_parser = parse_temperature, global = true)]
+    /// The sampling temperature, between 0 and 2. Defaults to $HOWTO_CLI_TEMPERATURE, then the config file, then 0.
+    temperature: Option<f32>,
+
+    #[arg(long, global = true)]
+    /// The maximum number of tokens to generate. Defaults to $HOWTO_CLI_MAX_TOKENS, then the config file, then 1024.
+    max_tokens: Option<u32>,
+}
+
+#[derive(Subcommand)]
+enum HowToCommand {
+    /// View or edit the config file.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Explain what an existing command does.
+    Explain {
+        /// The command to explain.
+        command: String,
+    },
+    /// Save API keys in the OS keyring, or in an encrypted file where there is none.
+    ///
+    /// Keys are saved for the provider chosen with --provider and the profile chosen with
+    /// --profile.
+    #[command(subcommand)]
+    Auth(AuthCommand),
+    /// Inspect or clear cached commands.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// List the recipes that answer common actions without asking the model.
+    ///
+    /// Add your own in YAML files in the directory printed by `howto recipes path`.
+    Recipes {
+        #[command(subcommand)]
+        command: Option<RecipesCommand>,
+    },
+    /// List previous queries. Reuse one with `howto !N`, or `howto !-1` for the latest.
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommand>,
+        #[arg(short = 'n', long, default_value_t = 20)]
+        /// How many of the most recent entries to show.
+        limit: usize,
+    },
+    /// Print a widget that turns the current line into a command when you press Ctrl-G.
+    ///
+    /// Add `eval "$(howto shell-init bash)"` to ~/.bashrc, `eval "$(howto shell-init zsh)"` to
+    /// ~/.zshrc, or `howto shell-init fish | source` to ~/.config/fish/config.fish.
+    ShellInit {
+        // `shell` is already the id of the global --shell flag.
+        #[arg(id = "init_shell", value_name = "SHELL", value_enum)]
+        shell: shell_init::InitShell,
+    },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Show entries whose action or command contains a term.
+    Search { term: String },
+}
+
+#[derive(Subcommand)]
+enum RecipesCommand {
+    /// Show the recipes that mention the words of a query, best first.
+    Search { query: String },
+    /// Print the directory your own recipes go in.
+    Path,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the value of a config key, e.g. `safety.policy`.
+    Get { key: String },
+    /// Set a config key.
+    Set { key: String, value: String },
+    /// Remove a config key.
+    Unset { key: String },
+    /// Print every key set in the config file.
+    List,
+    /// Print the path of the config file.
+    Path,
+}
+
+#[derive(Subcommand)]
+enum AuthCommand {
+    /// Save an API key, read from stdin or asked for on the terminal.
+    Login {
+        #[arg(long, value_enum)]
+        /// Where to save the key. Defaults to the keyring if there is one, then the encrypted file.
+        store: Option<credentials::Store>,
+    },
+    /// Remove a saved API key.
+    Logout,
+    /// Show where each provider's API key comes from.
+    Status,
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove every cached command.
+    Clear,
+    /// Print how many commands are cached and how much space they use.
+    Stats,
+}
+
+/// The provider recorded in the history for commands that came from recipes.
+const RECIPE_PROVIDER: &str = "recipe";
+const DEFAULT_TEMPERATURE: f32 = 0.0;
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_DELAY_SECS: f64 = 1.0;
+const DEFAULT_MAX_DELAY_SECS: f64 = 30.0;
+
+fn parse_temperature(value: &str) -> Result<f32> {
+    let temperature: f32 = value.parse().context("Temperature must be a number.")?;
+    validate_temperature(temperature)
+}
+
+fn parse_header(value: &str) -> Result<(String, String)> {
+    let (name, value) = value
+        .split_once(':')
+        .context("Headers must be in the form 'Name: value'.")?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+fn validate_temperature(temperature: f32) -> Result<f32> {
+    if !(0.0..=2.0).contains(&temperature) {
+        anyhow::bail!("Temperature must be between 0 and 2.");
+    }
+    Ok(temperature)
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     let args = HowToCli::parse();
 
-    let result = cli(args).await;
+    // `cli` switches to the config file's format once it has been loaded, if there is no flag.
+    let mut format = args.format.unwrap_or_default();
+    let result = cli(args, &mut format).await;
 
-    if let Err(err) = result {
-        eprintln!("Error: {}", err);
-        std::process::exit(1);
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            match format {
+                // The prompt the user cancelled has already said so.
+                OutputFormat::Text if error::is_cancelled(&err) => {}
+                OutputFormat::Text => print_error(&err),
+                OutputFormat::Json => print_json_error(&err),
+            }
+            std::process::exit(error::exit_code(&err));
+        }
     }
 }
 
-async fn cli(args: HowToCli) -> Result<()> {
-    let action = args.action;
-    let openai = get_openai_client().await?;
-    let command = generate_command(&openai, &action).await?;
-    println!("{}", command);
-    Ok(())
+fn print_error(err: &anyhow::Error) {
+    eprintln!("Error: {}", err);
+    for cause in err.chain().skip(1) {
+        eprintln!("  Caused by: {}", cause);
+    }
 }
 
-const SYSTEM_MESSAGE: &'static str = r#"
-You are an expert Unix system operator. You have intimate and detailed knowledge of CLI tools, both old and new.
-
-When the user asks for a command that accomplishes a high-level action, you respond with a CLI command that accomplishes that action.
-
-Example input:
-<action>
-go to my home directory
-</action>
-
-Example output:
-<command>
-cd ~
-</command>
-
-If the action cannot be accomplished via the CLI, you must respond with:
-<no_command/>
-"#;
-
-async fn generate_command(openai: &OpenAIClient, action: &str) -> Result<String> {
-    let system_message: ChatCompletionRequestMessage =
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content(SYSTEM_MESSAGE)
-            .build()
-            .expect("system message is valid")
-            .into();
-
-    let user_message: ChatCompletionRequestMessage =
-        ChatCompletionRequestUserMessageArgs::default()
-            .content(format!("<action>\n{}\n</action>", action.trim()))
-            .build()
-            .expect("user message is valid")
-            .into();
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .model("gpt-4o-2024-08-06")
-        .max_tokens(1024u32)
-        .temperature(0.0)
-        .messages([system_message, user_message])
-        .build()
-        .expect("request is valid");
-
-    let mut response = openai
-        .chat()
-        .create(request)
-        .await
-        .context("Unable to generate command. OpenAI request failed.")?;
+/// Prints an error as a JSON object on stdout, so scripts only need to read one stream.
+fn print_json_error(err: &anyhow::Error) {
+    let error = serde_json::json!({
+        "error": {
+            "code": error::code(err),
+            "message": err.to_string(),
+            "causes": err.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>(),
+        }
+    });
+    println!("{}", error);
+}
 
-    let choice = response
-        .choices
-        .pop()
-        .context("Unable to generate command. No response from model.")?;
+/// The result of a query in `--format json`.
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    action: &'a str,
+    command: &'a str,
+    explanation: Option<&'a Explanation>,
+    /// `None` when the safety policy is off.
+    risk: Option<safety::Risk>,
+    model: &'a str,
+    usage: Option<Usage>,
+    /// Whether the command was reused without asking the model.
+    cached: bool,
+    /// Whether the command came from a recipe, named by `model`, without asking the model.
+    recipe: bool,
+}
 
-    let content = choice.message.content.unwrap_or_default();
+async fn cli(mut args: HowToCli, format: &mut OutputFormat) -> Result<i32> {
+    let data_dir = get_data_dir()?;
+    let config_path = data_dir.join(config::CONFIG_FILE);
 
-    // find <command>...</command> in content
-    // if cannot find assume no command
+    let command = match args.command.take() {
+        Some(HowToCommand::Config(command)) => return config_cli(command, &config_path).await,
+        Some(HowToCommand::ShellInit { shell }) => {
+            print!("{}", shell_init::script(shell));
+            return Ok(0);
+        }
+        Some(HowToCommand::Explain { command }) => Some(command),
+        Some(HowToCommand::Auth(command)) => {
+            let (config, profile) = load_config(&config_path, &args).await?;
+            return auth_cli(command, &args, &config, &profile, &data_dir).await;
+        }
+        Some(HowToCommand::Cache(command)) => {
+            let config = Config::load(&config_path).await?;
+            return cache_cli(command, &get_cache(&data_dir, &config)).await;
+        }
+        Some(HowToCommand::Recipes { command }) => {
+            let config = Config::load(&config_path).await?;
+            return recipes_cli(command, &args, &config, &data_dir).await;
+        }
+        Some(HowToCommand::History { command, limit }) => {
+            let config = Config::load(&config_path).await?;
+            return history_cli(command, limit, &get_history(&data_dir, &config)).await;
+        }
+        None => None,
+    };
+
+    let (config, profile) = load_config(&config_path, &args).await?;
+    let history = get_history(&data_dir, &config);
+
+    *format = args.format.or(config.output.format).unwrap_or_default();
+    if *format == OutputFormat::Json && (args.run || args.interactive) {
+        anyhow::bail!("--run and --interactive cannot be used with JSON output.");
+    }
+    let format = *format;
 
-    let start_index = content.find("<command>").map(|i| i + "<command>".len());
-    let end_index = content.find("</command>");
+    if let Some(number) = args
+        .action
+        .as_deref()
+        .and_then(|action| action.strip_prefix('!'))
+        .and_then(|number| number.parse().ok())
+    {
+        return reuse_cli(number, &args, &config, &history, format).await;
+    }
+
+    let shell = get_shell(args.shell, &config);
+    let dialect = Dialect::detect(&shell);
+
+    // Recipes stand in for a single command, not for alternatives or a conversation.
+    let use_recipes = config.recipes.enabled.unwrap_or(true)
+        && !args.no_recipes
+        && command.is_none()
+        && !args.interactive
+        && args.alternatives == 1;
+    let recipes = match use_recipes {
+        true => Some(Recipes::load(&data_dir.join(recipes::RECIPES_DIR)).await?),
+        false => None,
+    };
+    let recipe = recipes
+        .as_ref()
+        .zip(args.action.as_deref())
+        .and_then(|(recipes, action)| recipes.find(action, dialect));
+
+    // Only an explanation of a recipe needs the model.
+    let needs_model = recipe.is_none() || args.explain;
+    let (provider_config, fallback) =
+        get_provider_config(&args, &config, &profile, needs_model).await?;
+    let context = PromptContext {
+        system_message: generate::system_message(&config, dialect),
+        environment: config
+            .prompt
+            .environment
+            .unwrap_or(true)
+            .then(|| Environment::detect(&shell)),
+        dialect,
+    };
 
-    if let (Some(start), Some(end)) = (start_index, end_index) {
-        Ok(content[start..end].trim().to_string())
+    // Everything besides the action that changes what the model would say.
+    let cache_key_parts = [
+        ("provider", provider_config.kind.name().to_string()),
+        (
+            "api_base",
+            provider_config.api_base.clone().unwrap_or_default(),
+        ),
+        ("model", provider_config.model.clone()),
+        ("temperature", provider_config.temperature.to_string()),
+        ("shell", shell.clone()),
+        ("os", env::consts::OS.to_string()),
+        (
+            "environment",
+            context
+                .environment
+                .as_ref()
+                .map(Environment::to_prompt)
+                .unwrap_or_default(),
+        ),
+        ("system", context.system_message.clone()),
+        ("explain", args.explain.to_string()),
+        ("alternatives", args.alternatives.to_string()),
+    ];
+
+    // Commands from recipes are recorded as such, with the page or file they came from.
+    let (provider_name, model) = match &recipe {
+        Some(recipe) => (RECIPE_PROVIDER, recipe.recipe.source.clone()),
+        None => (provider_config.kind.name(), provider_config.model.clone()),
+    };
+    let generator = provider::build(provider_config, fallback)?;
+    let timeout = get_timeout(&args, &config)?;
+
+    if let Some(command) = command {
+        let explanation = with_deadline(
+            timeout,
+            generate::explain_command(generator.as_ref(), &context, &command),
+        )
+        .await?;
+        match format {
+            OutputFormat::Text => println!("{}", explanation.render()),
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::json!({ "command": command, "explanation": explanation })
+            ),
+        }
+        return Ok(0);
+    }
+
+    let session = Session {
+        args: &args,
+        config: &config,
+        history: &history,
+        generator: generator.as_ref(),
+        context: &context,
+        shell: &shell,
+        provider_name,
+        model: &model,
+        timeout,
+    };
+
+    if args.interactive {
+        return interactive_cli(&session).await;
+    }
+
+    let action = args.action.as_deref().expect("clap requires an action");
+    // Stream the command to the terminal as it is generated. Pipes get a single clean line once
+    // the response is complete, and lists of alternatives are shown all at once.
+    let stream = args.alternatives == 1
+        && !args.run
+        && format == OutputFormat::Text
+        && io::stdout().is_terminal();
+    let mut printer = CommandPrinter::new();
+    let mut on_delta = |delta: &str| printer.push(delta);
+
+    let cache = get_cache(&data_dir, &config);
+    let use_cache = config.cache.enabled.unwrap_or(true);
+    let cache_key = cache::key(action, &cache_key_parts);
+
+    let cached = if recipe.is_none() && use_cache && !args.no_cache {
+        cache.get(&cache_key).await
     } else {
-        anyhow::bail!("No command could be generated for the action.")
+        None
+    };
+
+    let (generation, cached) = match (&recipe, cached) {
+        (Some(recipe), _) => {
+            let explanation = match args.explain {
+                true => Some(
+                    with_deadline(
+                        timeout,
+                        generate::explain_command(generator.as_ref(), &context, &recipe.command),
+                    )
+                    .await?,
+                ),
+                false => None,
+            };
+            let generated = Generated {
+                command: recipe.command.clone(),
+                rationale: None,
+                explanation,
+            };
+            (
+                Generation {
+                    commands: vec![generated],
+                    usage: None,
+                },
+                false,
+            )
+        }
+        (None, Some(commands)) => (
+            Generation {
+                commands,
+                usage: None,
+            },
+            true,
+        ),
+        (None, None) => {
+            let generation = with_deadline(
+                timeout,
+                generate::generate_command(
+                    generator.as_ref(),
+                    &context,
+                    &mut Vec::new(),
+                    action,
+                    args.explain,
+                    args.alternatives,
+                    stream.then_some(&mut on_delta as _),
+                ),
+            )
+            .await?;
+
+            // A fallback model's answer should not stand in for the provider's next time.
+            if use_cache && generator.fallback().is_none() {
+                if let Err(err) = cache.put(&cache_key, &generation.commands).await {
+                    eprintln!("Unable to cache the generated command: {:#}", err);
+                }
+            }
+            (generation, false)
+        }
+    };
+
+    let Some(generated) = choose_command(generation.commands)? else {
+        return Err(error::Error::Cancelled.into());
+    };
+    let command = generated.command.as_str();
+    let policy = config.safety.policy.unwrap_or_default();
+    let (provider_name, model) = session.answering();
+
+    if format == OutputFormat::Json {
+        let output = JsonOutput {
+            action,
+            command,
+            explanation: generated.explanation.as_ref(),
+            risk: (policy != SafetyPolicy::Off).then(|| safety::analyze(command).risk),
+            model,
+            usage: generation.usage,
+            cached,
+            recipe: recipe.is_some(),
+        };
+        println!("{}", serde_json::to_string(&output)?);
+        if args.copy {
+            copy_command(command);
+        }
+        let entry = history::Entry::new(action, command, provider_name, model, false);
+        record(&history, &config, &entry).await;
+        return Ok(0);
+    }
+
+    // A streamed command is already on the terminal, so its line is ended before anything else
+    // is printed, and any warning goes below it.
+    let streamed = !printer.printed().is_empty();
+    if streamed {
+        print_command(command, &printer);
+    }
+    if let Some(recipe) = &recipe {
+        eprintln!(
+            "From the {} recipe. Pass --no-recipes to ask the model instead.",
+            recipe.recipe.source
+        );
+    }
+    if let Some(explanation) = &generated.explanation {
+        eprintln!("{}\n", explanation.render());
+    }
+    let assessment = check_safety(command, policy);
+
+    if !args.run {
+        if !streamed {
+            print_command(command, &printer);
+        }
+        if args.copy {
+            copy_command(command);
+        }
+        let entry = history::Entry::new(action, command, provider_name, model, false);
+        record(&history, &config, &entry).await;
+        return Ok(0);
+    }
+
+    confirm_and_run(&session, action, command, assessment.as_ref()).await
+}
+
+/// Everything needed to generate, print and run commands once the provider is set up.
+struct Session<'a> {
+    args: &'a HowToCli,
+    config: &'a Config,
+    history: &'a History,
+    generator: &'a dyn CommandGenerator,
+    context: &'a PromptContext,
+    shell: &'a str,
+    provider_name: &'a str,
+    model: &'a str,
+    /// How long to wait for each command, including retries.
+    timeout: Option<Duration>,
+}
+
+impl Session<'_> {
+    /// The provider and model answering, which are the local ones once the provider has been
+    /// found unreachable.
+    fn answering(&self) -> (&str, &str) {
+        match self.generator.fallback() {
+            Some((kind, model)) => (kind.name(), model),
+            None => (self.provider_name, self.model),
+        }
     }
 }
 
-async fn get_openai_client() -> Result<OpenAIClient> {
-    let api_key = get_api_key().await?;
-    let config = OpenAIConfig::new().with_api_key(api_key);
-    Ok(OpenAIClient::with_config(config))
+/// Reads actions from stdin, each one refining the commands generated before it.
+async fn interactive_cli(session: &Session<'_>) -> Result<i32> {
+    eprintln!(
+        "Describe an action, then refine the command with follow-ups like \"make it recursive\"."
+    );
+    eprintln!("Type :run to run the last command, :reset to start over or :quit to exit.");
+
+    let policy = session.config.safety.policy.unwrap_or_default();
+    let mut conversation = Vec::new();
+    let mut last: Option<(String, String)> = None;
+    let mut pending = session.args.action.clone();
+
+    loop {
+        let line = match pending.take() {
+            Some(action) => action,
+            None => match run::read_line("howto> ")? {
+                Some(line) => line,
+                None => {
+                    eprintln!();
+                    return Ok(0);
+                }
+            },
+        };
+
+        match line.trim() {
+            "" => {}
+            ":quit" | ":q" => return Ok(0),
+            ":reset" => {
+                conversation.clear();
+                last = None;
+            }
+            ":run" | ":r" => {
+                let Some((action, command)) = &last else {
+                    eprintln!("Nothing to run yet.");
+                    continue;
+                };
+                let assessment = check_safety(command, policy);
+                match confirm_and_run(session, action, command, assessment.as_ref()).await {
+                    Ok(0) => {}
+                    Ok(code) => eprintln!("Exited with code {}.", code),
+                    Err(err) if error::is_cancelled(&err) => {}
+                    Err(err) => print_error(&err),
+                }
+            }
+            action => match refine(session, &mut conversation, action).await {
+                Ok(Some(command)) => last = Some((action.to_string(), command)),
+                Ok(None) => {}
+                Err(err) => print_error(&err),
+            },
+        }
+    }
+}
+
+/// Generates a command for one action in an interactive session and prints it.
+async fn refine(
+    session: &Session<'_>,
+    conversation: &mut Vec<provider::Message>,
+    action: &str,
+) -> Result<Option<String>> {
+    let stream = session.args.alternatives == 1 && io::stdout().is_terminal();
+    let mut printer = CommandPrinter::new();
+    let mut on_delta = |delta: &str| printer.push(delta);
+
+    let generation = with_deadline(
+        session.timeout,
+        generate::generate_command(
+            session.generator,
+            session.context,
+            conversation,
+            action,
+            session.args.explain,
+            session.args.alternatives,
+            stream.then_some(&mut on_delta as _),
+        ),
+    )
+    .await?;
+
+    let Some(generated) = choose_command(generation.commands)? else {
+        return Ok(None);
+    };
+    let command = generated.command;
+
+    let streamed = !printer.printed().is_empty();
+    if streamed {
+        print_command(&command, &printer);
+    }
+    if let Some(explanation) = &generated.explanation {
+        eprintln!("{}\n", explanation.render());
+    }
+    check_safety(&command, session.config.safety.policy.unwrap_or_default());
+    if !streamed {
+        print_command(&command, &printer);
+    }
+    if session.args.copy {
+        copy_command(&command);
+    }
+
+    let (provider_name, model) = session.answering();
+    let entry = history::Entry::new(action, &command, provider_name, model, false);
+    record(session.history, session.config, &entry).await;
+
+    Ok(Some(command))
+}
+
+/// Lets the user pick one of several generated commands.
+///
+/// Returns `None` if the user cancelled.
+fn choose_command(mut generated: Vec<Generated>) -> Result<Option<Generated>> {
+    let chosen = if generated.len() > 1 {
+        let options = generated
+            .iter()
+            .map(|generated| (generated.command.as_str(), generated.rationale.as_deref()))
+            .collect::<Vec<_>>();
+        match run::choose(&options)? {
+            Some(index) => index,
+            None => {
+                eprintln!("Cancelled.");
+                return Ok(None);
+            }
+        }
+    } else {
+        0
+    };
+
+    Ok(Some(generated.swap_remove(chosen)))
+}
+
+/// Prints the chosen command, finishing the line if it was already streamed to the terminal.
+fn print_command(command: &str, printer: &CommandPrinter) {
+    // The streamed command may differ if the model had to correct it.
+    if printer.printed() == command {
+        println!();
+    } else if !printer.printed().is_empty() {
+        println!("\n{}", command);
+    } else {
+        println!("{}", command);
+    }
+}
+
+/// Copies the command to the clipboard. Failing to do so is not fatal, as it has been printed.
+fn copy_command(command: &str) {
+    match clipboard::copy(command) {
+        Ok(()) => eprintln!("Copied to the clipboard."),
+        Err(err) => eprintln!("{:#}", err),
+    }
+}
+
+/// Asks the user to confirm a command, records the outcome in the history and runs it.
+async fn confirm_and_run(
+    session: &Session<'_>,
+    action: &str,
+    command: &str,
+    assessment: Option<&safety::Assessment>,
+) -> Result<i32> {
+    let policy = session.config.safety.policy.unwrap_or_default();
+    let confirmed = confirm_command(command, assessment, policy, session.args.force)?;
+
+    let (provider_name, model) = session.answering();
+    let entry = history::Entry::new(
+        action,
+        confirmed.as_deref().unwrap_or(command),
+        provider_name,
+        model,
+        confirmed.is_some(),
+    );
+    record(session.history, session.config, &entry).await;
+
+    match confirmed {
+        Some(command) => run::execute(session.shell, &command),
+        None => Err(error::Error::Cancelled.into()),
+    }
+}
+
+/// Asks the user to confirm a command before it is run, checking it again if they edit it.
+///
+/// Returns the command to run, or `None` if the user cancelled.
+fn confirm_command(
+    command: &str,
+    assessment: Option<&safety::Assessment>,
+    policy: SafetyPolicy,
+    force: bool,
+) -> Result<Option<String>> {
+    ensure_runnable(assessment, policy, force)?;
+
+    match run::confirm(command)? {
+        run::Confirmation::Run(edited) => {
+            // The user may have edited the command into something dangerous.
+            if edited != command {
+                let assessment = check_safety(&edited, policy);
+                ensure_runnable(assessment.as_ref(), policy, force)?;
+            }
+            Ok(Some(edited))
+        }
+        run::Confirmation::Cancel => {
+            eprintln!("Cancelled.");
+            Ok(None)
+        }
+    }
+}
+
+/// Prints or runs the command from a history entry. Negative numbers count back from the latest.
+async fn reuse_cli(
+    number: i64,
+    args: &HowToCli,
+    config: &Config,
+    history: &History,
+    format: OutputFormat,
+) -> Result<i32> {
+    let Some(entry) = history.find(number).await? else {
+        anyhow::bail!(
+            "History entry {} does not exist. Run `howto history` to list entries.",
+            number
+        );
+    };
+
+    let policy = config.safety.policy.unwrap_or_default();
+
+    if format == OutputFormat::Json {
+        let output = JsonOutput {
+            action: &entry.action,
+            command: &entry.command,
+            explanation: None,
+            risk: (policy != SafetyPolicy::Off).then(|| safety::analyze(&entry.command).risk),
+            model: &entry.model,
+            usage: None,
+            cached: true,
+            recipe: entry.provider == RECIPE_PROVIDER,
+        };
+        println!("{}", serde_json::to_string(&output)?);
+        return Ok(0);
+    }
+
+    let assessment = check_safety(&entry.command, policy);
+    if !args.run {
+        println!("{}", entry.command);
+        return Ok(0);
+    }
+
+    let Some(command) = confirm_command(&entry.command, assessment.as_ref(), policy, args.force)?
+    else {
+        return Err(error::Error::Cancelled.into());
+    };
+
+    let rerun = history::Entry::new(&entry.action, &command, &entry.provider, &entry.model, true);
+    record(history, config, &rerun).await;
+
+    run::execute(&get_shell(args.shell, config), &command)
+}
+
+/// The shell used to run commands. `--shell` wins over the config file and `$SHELL`.
+fn get_shell(dialect: Option<Dialect>, config: &Config) -> String {
+    let shell = config.shell.clone().unwrap_or_else(run::default_shell);
+
+    match dialect {
+        Some(dialect) if dialect != Dialect::detect(&shell) => dialect.program().to_string(),
+        _ => shell,
+    }
+}
+
+async fn auth_cli(
+    command: AuthCommand,
+    args: &HowToCli,
+    config: &Config,
+    profile: &str,
+    data_dir: &Path,
+) -> Result<i32> {
+    let credentials = Credentials::new(data_dir);
+    let kind = get_provider_kind(args, config)?;
+
+    match command {
+        AuthCommand::Login { store } => {
+            if !kind.uses_api_key() {
+                anyhow::bail!("The {} provider does not use an API key.", kind.name());
+            }
+
+            // A key piped in, e.g. from a password manager, needs no prompt.
+            let prompt = match io::stdin().is_terminal() {
+                true => format!("API key for {}: ", kind.name()),
+                false => String::new(),
+            };
+            let api_key = run::read_secret(&prompt)?.unwrap_or_default();
+            let api_key = api_key.trim();
+            if api_key.is_empty() {
+                anyhow::bail!("No API key was given.");
+            }
+
+            let store = credentials.set(profile, kind, api_key, store)?;
+            eprintln!(
+                "Saved the {} API key for profile {} in {}.",
+                kind.name(),
+                profile,
+                store.describe()
+            );
+        }
+        AuthCommand::Logout => {
+            let removed = credentials.delete(profile, kind)?;
+            if removed.is_empty() {
+                eprintln!(
+                    "No {} API key is saved for profile {}.",
+                    kind.name(),
+                    profile
+                );
+            } else {
+                let stores = removed
+                    .iter()
+                    .map(|store| store.describe())
+                    .collect::<Vec<_>>();
+                eprintln!(
+                    "Removed the {} API key for profile {} from {}.",
+                    kind.name(),
+                    profile,
+                    stores.join(" and ")
+                );
+            }
+        }
+        AuthCommand::Status => {
+            println!("Profile: {}", profile);
+            for &kind in ProviderKind::value_variants() {
+                let source = match get_env_var(&api_key_env_var(kind))? {
+                    Some(_) => format!("{} environment variable", api_key_env_var(kind)),
+                    None if !kind.uses_api_key() => "not required".to_string(),
+                    None if config.api_key_command(kind).is_some() => "api_key_command".to_string(),
+                    None => match credentials.locate(profile, kind)? {
+                        Some(store) => store.describe().to_string(),
+                        None => {
+                            let path = data_dir.join(api_key_file(kind));
+                            if profile == credentials::DEFAULT_PROFILE && path.exists() {
+                                credentials::check_permissions(&path);
+                                format!("{} (plaintext)", path.display())
+                            } else {
+                                "not set".to_string()
+                            }
+                        }
+                    },
+                };
+                println!("  {:<18} {}", kind.name(), source);
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+async fn config_cli(command: ConfigCommand, path: &Path) -> Result<i32> {
+    match command {
+        ConfigCommand::Get { key } => match config::get(path, &key).await? {
+            Some(value) => println!("{}", config::display_value(&value)),
+            None => anyhow::bail!("{} is not set.", key),
+        },
+        ConfigCommand::Set { key, value } => config::set(path, &key, &value).await?,
+        ConfigCommand::Unset { key } => config::unset(path, &key).await?,
+        ConfigCommand::List => {
+            for (key, value) in config::list(path).await? {
+                println!("{} = {}", key, value);
+            }
+        }
+        ConfigCommand::Path => println!("{}", path.display()),
+    }
+
+    Ok(0)
+}
+
+async fn cache_cli(command: CacheCommand, cache: &Cache) -> Result<i32> {
+    match command {
+        CacheCommand::Clear => {
+            let removed = cache.clear().await?;
+            println!(
+                "Removed {} cached {}.",
+                removed,
+                if removed == 1 { "command" } else { "commands" }
+            );
+        }
+        CacheCommand::Stats => {
+            let stats = cache.stats().await?;
+            println!("Location: {}", cache.dir().display());
+            println!("Entries:  {} ({} expired)", stats.entries, stats.expired);
+            println!("Size:     {:.1} KiB", stats.bytes as f64 / 1024.0);
+        }
+    }
+
+    Ok(0)
+}
+
+async fn history_cli(
+    command: Option<HistoryCommand>,
+    limit: usize,
+    history: &History,
+) -> Result<i32> {
+    let entries = history.load().await?;
+
+    // Entries are shown with their ids so the numbers work with `howto !N`.
+    let mut matches = entries.iter().collect::<Vec<_>>();
+    if let Some(HistoryCommand::Search { term }) = command {
+        let term = term.to_lowercase();
+        matches.retain(|entry| {
+            entry.action.to_lowercase().contains(&term)
+                || entry.command.to_lowercase().contains(&term)
+        });
+    }
+
+    let skip = matches.len().saturating_sub(limit);
+    for entry in &matches[skip..] {
+        println!(
+            "{:>5}  {}  {}{}",
+            entry.id,
+            history::format_timestamp(entry.timestamp),
+            entry.action,
+            if entry.executed { "  (ran)" } else { "" }
+        );
+        println!("       {}", entry.command);
+    }
+
+    Ok(0)
+}
+
+async fn recipes_cli(
+    command: Option<RecipesCommand>,
+    args: &HowToCli,
+    config: &Config,
+    data_dir: &Path,
+) -> Result<i32> {
+    let dir = data_dir.join(recipes::RECIPES_DIR);
+    if let Some(RecipesCommand::Path) = command {
+        println!("{}", dir.display());
+        return Ok(0);
+    }
+
+    let recipes = Recipes::load(&dir).await?;
+    let dialect = Dialect::detect(&get_shell(args.shell, config));
+    let shown = match &command {
+        Some(RecipesCommand::Search { query }) => recipes
+            .search(query, dialect)
+            .into_iter()
+            .take(10)
+            .map(|found| found.recipe)
+            .collect(),
+        _ => recipes.applicable(dialect).collect::<Vec<_>>(),
+    };
+
+    for recipe in shown {
+        println!("{}  ({})", recipe.actions.join(" / "), recipe.source);
+        println!("    {}", recipe.command.replace('\n', "\n    "));
+    }
+    Ok(0)
+}
+
+fn get_history(data_dir: &Path, config: &Config) -> History {
+    History::new(
+        data_dir.join(history::HISTORY_FILE),
+        config
+            .history
+            .max_entries
+            .unwrap_or(history::DEFAULT_MAX_ENTRIES),
+    )
+}
+
+/// Adds an entry to the history, unless it is turned off in the config file.
+async fn record(history: &History, config: &Config, entry: &history::Entry) {
+    if !config.history.enabled.unwrap_or(true) {
+        return;
+    }
+    if let Err(err) = history.append(entry).await {
+        eprintln!("Unable to save the command to the history: {:#}", err);
+    }
+}
+
+fn get_cache(data_dir: &Path, config: &Config) -> Cache {
+    Cache::new(
+        data_dir.join(cache::CACHE_DIR),
+        Duration::from_secs(config.cache.ttl.unwrap_or(cache::DEFAULT_TTL_SECS)),
+    )
+}
+
+/// Analyses a command and prints any warnings, unless the safety policy turns analysis off.
+fn check_safety(command: &str, policy: SafetyPolicy) -> Option<safety::Assessment> {
+    if policy == SafetyPolicy::Off {
+        return None;
+    }
+
+    let assessment = safety::analyze(command);
+    assessment.print_warning();
+    Some(assessment)
+}
+
+fn ensure_runnable(
+    assessment: Option<&safety::Assessment>,
+    policy: SafetyPolicy,
+    force: bool,
+) -> Result<()> {
+    let high_risk = assessment.is_some_and(|assessment| assessment.risk == safety::Risk::High);
+    if high_risk && policy == SafetyPolicy::Block && !force {
+        return Err(error::Error::UnsafeCommand.into());
+    }
+    Ok(())
+}
+
+/// Resolves provider settings, preferring command line flags, then environment variables, then
+/// the config file, then built-in defaults.
+///
+/// Also returns the local model to fall back to, if the config file has one.
+async fn get_provider_config(
+    args: &HowToCli,
+    config: &Config,
+    profile: &str,
+    needs_model: bool,
+) -> Result<(ProviderConfig, Option<ProviderConfig>)> {
+    let kind = get_provider_kind(args, config)?;
+
+    let model = args
+        .model
+        .clone()
+        .or(get_env_var(MODEL_ENV_VAR)?)
+        .or_else(|| config.model.clone())
+        .unwrap_or_else(|| kind.default_model().to_string());
+
+    let temperature = match args.temperature {
+        Some(temperature) => temperature,
+        None => match get_env_var(TEMPERATURE_ENV_VAR)? {
+            Some(value) => parse_temperature(&value)
+                .with_context(|| format!("Invalid {} environment variable", TEMPERATURE_ENV_VAR))?,
+            None => match config.temperature {
+                Some(temperature) => validate_temperature(temperature)
+                    .context("Invalid temperature in the config file")?,
+                None => DEFAULT_TEMPERATURE,
+            },
+        },
+    };
+
+    let max_tokens = match args.max_tokens {
+        Some(max_tokens) => max_tokens,
+        None => parse_env_var(MAX_TOKENS_ENV_VAR)?
+            .or(config.max_tokens)
+            .unwrap_or(DEFAULT_MAX_TOKENS),
+    };
+
+    let api_base = args
+        .api_base
+        .clone()
+        .or(get_env_var(API_BASE_ENV_VAR)?)
+        .or_else(|| config.api_base.clone());
+
+    let org_id = get_env_var(ORG_ID_ENV_VAR)?.or_else(|| config.org_id.clone());
+    let project_id = get_env_var(PROJECT_ID_ENV_VAR)?.or_else(|| config.project_id.clone());
+
+    // Headers from the command line are added to, and override, those in the config file.
+    // Header names are case-insensitive, so normalise them before merging.
+    let headers = config
+        .headers
+        .iter()
+        .chain(args.headers.iter().map(|(name, value)| (name, value)))
+        .map(|(name, value)| (name.to_lowercase(), value.clone()))
+        .collect();
+
+    let proxy = args
+        .proxy
+        .clone()
+        .or(get_env_var(PROXY_ENV_VAR)?)
+        .or_else(|| config.proxy.clone());
+
+    let request_timeout = match args.request_timeout {
+        Some(secs) => secs,
+        None => parse_env_var(REQUEST_TIMEOUT_ENV_VAR)?
+            .or(config.request_timeout)
+            .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS),
+    };
+
+    let retry = RetryPolicy {
+        max_attempts: config.retry.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS),
+        initial_delay: get_delay(config.retry.initial_delay, DEFAULT_INITIAL_DELAY_SECS)
+            .context("Invalid retry.initial_delay in the config file")?,
+        max_delay: get_delay(config.retry.max_delay, DEFAULT_MAX_DELAY_SECS)
+            .context("Invalid retry.max_delay in the config file")?,
+    };
+    if retry.max_attempts == 0 {
+        anyhow::bail!("retry.max_attempts in the config file must be at least 1.");
+    }
+
+    // Without a request to the provider there is no need for a key, and a password manager may
+    // not be able to unlock offline.
+    let api_key = match args.offline || !needs_model {
+        true => None,
+        false => get_api_key(kind, profile, config).await?,
+    };
+
+    let provider_config = ProviderConfig {
+        kind,
+        api_key,
+        api_base,
+        org_id,
+        project_id,
+        headers,
+        proxy,
+        request_timeout: Duration::from_secs(request_timeout),
+        model,
+        temperature,
+        max_tokens,
+        // Servers behind the OpenAI-compatible API vary too much to assume support.
+        structured_output: config
+            .structured_output
+            .unwrap_or(kind != ProviderKind::OpenAICompatible),
+        retry,
+    };
+
+    let offline = config.offline.as_ref();
+    if args.offline {
+        let local = get_local_config(args, offline, &provider_config)?;
+        return Ok((local, None));
+    }
+    let fallback = match offline {
+        Some(settings) if settings.fallback.unwrap_or(true) => {
+            Some(get_local_config(args, offline, &provider_config)?)
+        }
+        _ => None,
+    };
+    Ok((provider_config, fallback))
+}
+
+/// Settings for the local model in the `[offline]` section. Besides the server and model, they
+/// follow the provider's.
+fn get_local_config(
+    args: &HowToCli,
+    offline: Option<&OfflineConfig>,
+    provider: &ProviderConfig,
+) -> Result<ProviderConfig> {
+    let kind = offline
+        .and_then(|offline| offline.provider)
+        .unwrap_or(ProviderKind::Ollama);
+    if !kind.is_local() {
+        anyhow::bail!(
+            "offline.provider in the config file must be ollama or openai-compatible, not {}.",
+            kind.name()
+        );
+    }
+
+    let api_base = offline.and_then(|offline| offline.api_base.clone());
+    if kind == ProviderKind::OpenAICompatible && api_base.is_none() {
+        anyhow::bail!("offline.api_base in the config file is required for openai-compatible.");
+    }
+
+    // --model picks the local model with --offline, but the provider's model is no use to it.
+    let model = args
+        .model
+        .clone()
+        .filter(|_| args.offline)
+        .or_else(|| offline.and_then(|offline| offline.model.clone()))
+        .unwrap_or_else(|| kind.default_model().to_string());
+
+    Ok(ProviderConfig {
+        kind,
+        api_key: None,
+        api_base,
+        org_id: None,
+        project_id: None,
+        // Headers and proxies are for reaching the provider, not a server on this machine.
+        headers: BTreeMap::new(),
+        proxy: None,
+        request_timeout: provider.request_timeout,
+        model,
+        temperature: provider.temperature,
+        max_tokens: provider.max_tokens,
+        structured_output: kind != ProviderKind::OpenAICompatible,
+        retry: provider.retry,
+    })
+}
+
+/// The provider follows the same order as the other settings: flag, environment, config file.
+fn get_provider_kind(args: &HowToCli, config: &Config) -> Result<ProviderKind> {
+    Ok(match args.provider {
+        Some(kind) => kind,
+        None => get_provider_from_env()?
+            .or(config.provider)
+            .unwrap_or(ProviderKind::OpenAI),
+    })
+}
+
+/// Loads the config file with the selected profile applied, returning the profile's name.
+///
+/// Warns about a profile that is neither in `[profiles]` nor has saved keys, as it is most
+/// likely a typo that would otherwise quietly fall back to the top-level settings.
+async fn load_config(path: &Path, args: &HowToCli) -> Result<(Config, String)> {
+    let mut config = Config::load(path).await?;
+
+    let profile = args
+        .profile
+        .clone()
+        .or(get_env_var(PROFILE_ENV_VAR)?)
+        .or_else(|| config.profile.clone())
+        .unwrap_or_else(|| credentials::DEFAULT_PROFILE.to_string());
+    let has_profiles = !config.profiles.is_empty();
+    let defined = config.apply_profile(&profile);
+
+    if has_profiles && !defined && profile != credentials::DEFAULT_PROFILE {
+        let credentials = Credentials::new(&get_data_dir()?);
+        let has_keys = ProviderKind::value_variants()
+            .iter()
+            .filter(|kind| kind.uses_api_key())
+            .any(|&kind| matches!(credentials.locate(&profile, kind), Ok(Some(_))));
+        if !has_keys {
+            eprintln!(
+                "Warning: profile {} is not in [profiles] in the config file and has no saved API keys. Using the top-level settings.",
+                profile
+            );
+        }
+    }
+
+    Ok((config, profile))
+}
+
+fn get_delay(secs: Option<f64>, default: f64) -> Result<Duration> {
+    Ok(Duration::try_from_secs_f64(secs.unwrap_or(default))?)
+}
+
+/// The deadline for generating a command. `--timeout` wins over the environment and config file.
+fn get_timeout(args: &HowToCli, config: &Config) -> Result<Option<Duration>> {
+    let timeout = match args.timeout {
+        Some(secs) => Some(secs),
+        None => parse_env_var(TIMEOUT_ENV_VAR)?.or(config.timeout),
+    };
+    Ok(timeout.map(Duration::from_secs))
+}
+
+/// Fails with a timeout if the future does not finish in time, abandoning any request or retry
+/// still in flight.
+async fn with_deadline<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    tokio::time::timeout(timeout, future)
+        .await
+        .unwrap_or_else(|_| {
+            Err(error::Error::Timeout {
+                setting: "--timeout",
+            }
+            .into())
+        })
 }
 
 const DATA_DIR_ENV_VAR: &str = "HOWTO_CLI_DATA_DIR";
-const OPENAI_API_KEY_ENV_VAR: &str = "HOWTO_CLI_OPENAI_API_KEY";
+const PROVIDER_ENV_VAR: &str = "HOWTO_CLI_PROVIDER";
+const MODEL_ENV_VAR: &str = "HOWTO_CLI_MODEL";
+const TEMPERATURE_ENV_VAR: &str = "HOWTO_CLI_TEMPERATURE";
+const MAX_TOKENS_ENV_VAR: &str = "HOWTO_CLI_MAX_TOKENS";
+const API_BASE_ENV_VAR: &str = "HOWTO_CLI_API_BASE";
+const ORG_ID_ENV_VAR: &str = "HOWTO_CLI_OPENAI_ORG_ID";
+const PROJECT_ID_ENV_VAR: &str = "HOWTO_CLI_OPENAI_PROJECT_ID";
+const PROXY_ENV_VAR: &str = "HOWTO_CLI_PROXY";
+const REQUEST_TIMEOUT_ENV_VAR: &str = "HOWTO_CLI_REQUEST_TIMEOUT";
+const TIMEOUT_ENV_VAR: &str = "HOWTO_CLI_TIMEOUT";
+const PROFILE_ENV_VAR: &str = "HOWTO_CLI_PROFILE";
 const DEFAULT_DATA_DIR_NAME: &str = ".howto-cli";
 const OPENAI_API_KEY_FILE: &str = "credentials";
 
-async fn get_api_key() -> Result<String> {
-    match env::var(OPENAI_API_KEY_ENV_VAR) {
-        Ok(api_key) => Ok(api_key),
-        Err(VarError::NotPresent) => {
-            let data_dir = get_data_dir()?;
-            let api_key_path = data_dir.join(OPENAI_API_KEY_FILE);
-            tokio::fs::read_to_string(api_key_path)
-                .await
-                .context("Unable to read OpenAI API key from file")
-                .map(|key| key.trim().to_string())
-        }
+/// Reads an environment variable, treating an unset variable as `None`.
+fn get_env_var(name: &str) -> Result<Option<String>> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(VarError::NotPresent) => Ok(None),
         Err(VarError::NotUnicode(_)) => Err(anyhow::anyhow!(
             "The value of the {} environment variable is not valid Unicode.",
-            OPENAI_API_KEY_ENV_VAR
+            name
         )),
     }
 }
 
+fn parse_env_var<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    get_env_var(name)?
+        .map(|value| value.parse::<T>())
+        .transpose()
+        .with_context(|| format!("Invalid {} environment variable", name))
+}
+
+fn get_provider_from_env() -> Result<Option<ProviderKind>> {
+    get_env_var(PROVIDER_ENV_VAR)?
+        .map(|name| {
+            ProviderKind::from_str(&name, true).map_err(|_| {
+                anyhow::anyhow!(
+                    "Unknown provider '{}' in the {} environment variable.",
+                    name,
+                    PROVIDER_ENV_VAR
+                )
+            })
+        })
+        .transpose()
+}
+
+/// The environment variable holding the API key for a provider, e.g. `HOWTO_CLI_ANTHROPIC_API_KEY`.
+fn api_key_env_var(kind: ProviderKind) -> String {
+    format!(
+        "HOWTO_CLI_{}_API_KEY",
+        kind.name().to_uppercase().replace('-', "_")
+    )
+}
+
+/// The file in the data dir holding the API key for a provider.
+///
+/// OpenAI keeps the original `credentials` file name so existing setups keep working.
+fn api_key_file(kind: ProviderKind) -> String {
+    match kind {
+        ProviderKind::OpenAI => OPENAI_API_KEY_FILE.to_string(),
+        kind => format!("{}-{}", kind.name(), OPENAI_API_KEY_FILE),
+    }
+}
+
+/// Finds the API key for a provider: in its environment variable, then from `api_key_command`,
+/// then saved with `howto auth login`, then in the plaintext key file older versions used.
+async fn get_api_key(kind: ProviderKind, profile: &str, config: &Config) -> Result<Option<String>> {
+    let env_var = api_key_env_var(kind);
+    if let Some(api_key) = get_env_var(&env_var)? {
+        return Ok(Some(api_key));
+    }
+    if let Some(command) = config.api_key_command(kind) {
+        return credentials::api_key_from_command(command).map(Some);
+    }
+
+    let data_dir = get_data_dir()?;
+    if let Some(api_key) = Credentials::new(&data_dir).get(profile, kind)? {
+        return Ok(Some(api_key));
+    }
+
+    // The key file predates profiles, so it only stands in for the default one.
+    if profile == credentials::DEFAULT_PROFILE {
+        let api_key_path = data_dir.join(api_key_file(kind));
+        match tokio::fs::read_to_string(&api_key_path).await {
+            Ok(key) => {
+                credentials::check_permissions(&api_key_path);
+                return Ok(Some(key.trim().to_string()));
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!(
+                        "Unable to read {} API key from {}",
+                        kind.name(),
+                        api_key_path.display()
+                    )
+                })
+            }
+        }
+    }
+
+    if !kind.requires_api_key() {
+        return Ok(None);
+    }
+    Err(error::Error::MissingCredentials {
+        provider: kind.name().to_string(),
+        profile: profile.to_string(),
+        env_var,
+    }
+    .into())
+}
+
 fn get_data_dir() -> Result<PathBuf> {
     match env::var(DATA_DIR_ENV_VAR) {
         Ok(data_dir) => Ok(PathBuf::from(data_dir)),
@@ -0,0 +1,276 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    for_each_line, request_error, status_error, CommandGenerator, Completion, Message,
+    ProviderConfig, Role, Schema, Usage,
+};
+
+const DEFAULT_API_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Talks to Anthropic's messages API.
+pub struct AnthropicGenerator {
+    http: reqwest::Client,
+    api_key: String,
+    api_base: String,
+    model: String,
+    temperature: f32,
+    max_tokens: u32,
+    structured_output: bool,
+}
+
+impl AnthropicGenerator {
+    pub fn new(config: ProviderConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            api_key: config.api_key.unwrap_or_default(),
+            api_base: config
+                .api_base
+                .unwrap_or_else(|| DEFAULT_API_BASE.to_string()),
+            model: config.model,
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            structured_output: config.structured_output,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<RequestMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Tool<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice<'a>>,
+}
+
+/// Structured output is done by forcing the model to call a tool whose input is the schema.
+#[derive(Serialize)]
+struct Tool<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ToolChoice<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct RequestMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    usage: Option<ResponseUsage>,
+}
+
+#[derive(Deserialize)]
+struct ResponseUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// The subset of streamed server-sent events that carry text.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    ContentBlockDelta {
+        delta: TextDelta,
+    },
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct TextDelta {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+impl AnthropicGenerator {
+    async fn send(
+        &self,
+        messages: &[Message],
+        stream: bool,
+        schema: Option<&Schema>,
+    ) -> Result<reqwest::Response> {
+        // Anthropic takes the system prompt as a top-level field rather than a message.
+        let system = messages
+            .iter()
+            .filter(|message| message.role == Role::System)
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>();
+
+        let body = MessagesRequest {
+            model: &self.model,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            system: (!system.is_empty()).then(|| system.join("\n")),
+            messages: messages
+                .iter()
+                .filter_map(|message| {
+                    let role = match message.role {
+                        Role::System => return None,
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    };
+                    Some(RequestMessage {
+                        role,
+                        content: &message.content,
+                    })
+                })
+                .collect(),
+            stream,
+            tools: schema
+                .map(|schema| Tool {
+                    name: schema.name,
+                    description: schema.description,
+                    input_schema: &schema.schema,
+                })
+                .into_iter()
+                .collect(),
+            tool_choice: schema.map(|schema| ToolChoice {
+                kind: "tool",
+                name: schema.name,
+            }),
+        };
+
+        let response = self
+            .http
+            .post(format!("{}/messages", self.api_base.trim_end_matches('/')))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body)
+            .send()
+            .await
+            .map_err(request_error)
+            .context("Unable to generate command. Anthropic request failed.")?;
+
+        if !response.status().is_success() {
+            return Err(status_error("Anthropic", response).await);
+        }
+
+        Ok(response)
+    }
+
+    /// Sends a non-streaming request. The input of a tool call is returned as JSON text.
+    async fn receive(&self, messages: &[Message], schema: Option<&Schema>) -> Result<Completion> {
+        let response: MessagesResponse = self
+            .send(messages, false, schema)
+            .await?
+            .json()
+            .await
+            .context("Unable to generate command. Anthropic response was malformed.")?;
+
+        let content = response
+            .content
+            .into_iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text),
+                ContentBlock::ToolUse { input } => Some(input.to_string()),
+                ContentBlock::Other => None,
+            })
+            .collect::<Vec<_>>()
+            .join("");
+
+        Ok(Completion {
+            content,
+            usage: response.usage.map(|usage| Usage {
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for AnthropicGenerator {
+    async fn complete(&self, messages: &[Message]) -> Result<Completion> {
+        self.receive(messages, None).await
+    }
+
+    async fn complete_structured(
+        &self,
+        messages: &[Message],
+        schema: &Schema,
+    ) -> Result<Option<Completion>> {
+        if !self.structured_output {
+            return Ok(None);
+        }
+
+        self.receive(messages, Some(schema)).await.map(Some)
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        on_delta: &mut (dyn for<'a> FnMut(&'a str) + Send),
+    ) -> Result<Completion> {
+        let response = self.send(messages, true, None).await?;
+        let mut content = String::new();
+
+        for_each_line(response, |line| {
+            let Some(data) = line.strip_prefix("data:") else {
+                return Ok(());
+            };
+
+            let event: StreamEvent = serde_json::from_str(data.trim())
+                .context("Unable to generate command. Anthropic response was malformed.")?;
+
+            match event {
+                StreamEvent::ContentBlockDelta { delta } => {
+                    on_delta(&delta.text);
+                    content.push_str(&delta.text);
+                }
+                StreamEvent::Error { error } => {
+                    anyhow::bail!("Unable to generate command. {}", error.message)
+                }
+                StreamEvent::Other => {}
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(Completion {
+            content,
+            usage: None,
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{CommandGenerator, Completion, Message, ProviderKind, Schema};
+use crate::error;
+
+/// Sends requests to a model on this machine when the provider cannot be reached, e.g. on a
+/// plane or an air-gapped host.
+pub struct FallbackGenerator {
+    primary: Box<dyn CommandGenerator>,
+    provider: ProviderKind,
+    local: Box<dyn CommandGenerator>,
+    local_provider: ProviderKind,
+    local_model: String,
+    /// Set once the provider has been found unreachable, so later requests go straight to the
+    /// local model instead of waiting for the provider to fail again.
+    offline: AtomicBool,
+}
+
+impl FallbackGenerator {
+    pub fn new(
+        primary: Box<dyn CommandGenerator>,
+        provider: ProviderKind,
+        local: Box<dyn CommandGenerator>,
+        local_provider: ProviderKind,
+        local_model: String,
+    ) -> Self {
+        Self {
+            primary,
+            provider,
+            local,
+            local_provider,
+            local_model,
+            offline: AtomicBool::new(false),
+        }
+    }
+
+    fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    /// Whether a failed request should be sent to the local model, switching to it if so.
+    fn fall_back(&self, err: &anyhow::Error) -> bool {
+        if !error::find(err).is_some_and(|err| err.is_unreachable()) {
+            return false;
+        }
+        eprintln!(
+            "Unable to reach {}, using the local model {} instead.",
+            self.provider.name(),
+            self.local_model
+        );
+        self.offline.store(true, Ordering::Relaxed);
+        true
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for FallbackGenerator {
+    async fn complete(&self, messages: &[Message]) -> Result<Completion> {
+        if !self.is_offline() {
+            match self.primary.complete(messages).await {
+                Err(err) if self.fall_back(&err) => {}
+                result => return result,
+            }
+        }
+        self.local.complete(messages).await
+    }
+
+    async fn complete_structured(
+        &self,
+        messages: &[Message],
+        schema: &Schema,
+    ) -> Result<Option<Completion>> {
+        if !self.is_offline() {
+            match self.primary.complete_structured(messages, schema).await {
+                Err(err) if self.fall_back(&err) => {}
+                result => return result,
+            }
+        }
+        self.local.complete_structured(messages, schema).await
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        on_delta: &mut (dyn for<'a> FnMut(&'a str) + Send),
+    ) -> Result<Completion> {
+        if !self.is_offline() {
+            let mut streamed = false;
+            let mut on_primary_delta = |delta: &str| {
+                streamed |= !delta.is_empty();
+                on_delta(delta);
+            };
+
+            match self
+                .primary
+                .complete_stream(messages, &mut on_primary_delta)
+                .await
+            {
+                // The local model would start the command over on top of the partial one.
+                Err(err) if !streamed && self.fall_back(&err) => {}
+                result => return result,
+            }
+        }
+        self.local.complete_stream(messages, on_delta).await
+    }
+
+    fn fallback(&self) -> Option<(ProviderKind, &str)> {
+        self.is_offline()
+            .then_some((self.local_provider, self.local_model.as_str()))
+    }
+}
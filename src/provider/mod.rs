@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use clap::ValueEnum;
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+mod anthropic;
+mod fallback;
+mod ollama;
+mod openai;
+mod retry;
+
+pub use anthropic::AnthropicGenerator;
+pub use fallback::FallbackGenerator;
+pub use ollama::OllamaGenerator;
+pub use openai::OpenAIGenerator;
+pub use retry::{RetryPolicy, RetryingGenerator};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// A single message in a conversation with a model.
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// Tokens used by a request, as reported by the provider.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl Usage {
+    /// Adds up the usage of several requests. `None` means the provider did not report any.
+    pub fn sum(usages: impl IntoIterator<Item = Option<Usage>>) -> Option<Usage> {
+        usages.into_iter().flatten().reduce(|total, usage| Usage {
+            input_tokens: total.input_tokens + usage.input_tokens,
+            output_tokens: total.output_tokens + usage.output_tokens,
+        })
+    }
+}
+
+/// A model's reply.
+pub struct Completion {
+    pub content: String,
+    /// Only reported for complete, non-streamed, responses.
+    pub usage: Option<Usage>,
+}
+
+/// A JSON schema that a reply must follow.
+pub struct Schema {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub schema: serde_json::Value,
+}
+
+/// A model backend that can complete a conversation.
+///
+/// Implementations only deal with transport; prompting and response parsing live in
+/// `generate_command` so every provider speaks the same `<command>` protocol.
+#[async_trait]
+pub trait CommandGenerator: Send + Sync {
+    /// Sends the conversation to the model and returns its reply.
+    async fn complete(&self, messages: &[Message]) -> Result<Completion>;
+
+    /// Like `complete`, but has the provider constrain the reply to JSON matching `schema`.
+    ///
+    /// Returns `None` if the provider cannot do that, or structured output is turned off, in which
+    /// case the caller should fall back to `complete`.
+    async fn complete_structured(
+        &self,
+        _messages: &[Message],
+        _schema: &Schema,
+    ) -> Result<Option<Completion>> {
+        Ok(None)
+    }
+
+    /// Like `complete`, but calls `on_delta` with each piece of the reply as it arrives.
+    ///
+    /// Providers without streaming support deliver the whole reply as a single piece.
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        on_delta: &mut (dyn for<'a> FnMut(&'a str) + Send),
+    ) -> Result<Completion> {
+        let completion = self.complete(messages).await?;
+        on_delta(&completion.content);
+        Ok(completion)
+    }
+
+    /// The provider and model that took over because this one could not be reached, if any.
+    fn fallback(&self) -> Option<(ProviderKind, &str)> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// OpenAI's hosted API.
+    #[value(name = "openai")]
+    #[serde(rename = "openai")]
+    OpenAI,
+    /// Anthropic's hosted API.
+    Anthropic,
+    /// A local Ollama server.
+    Ollama,
+    /// Any server implementing the OpenAI chat completions API, e.g. llama.cpp or vLLM.
+    #[value(name = "openai-compatible")]
+    #[serde(rename = "openai-compatible")]
+    OpenAICompatible,
+}
+
+impl ProviderKind {
+    /// The name used for this provider on the command line and in environment variables.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "openai",
+            ProviderKind::Anthropic => "anthropic",
+            ProviderKind::Ollama => "ollama",
+            ProviderKind::OpenAICompatible => "openai-compatible",
+        }
+    }
+
+    /// Whether this provider sends an API key at all.
+    pub fn uses_api_key(self) -> bool {
+        self != ProviderKind::Ollama
+    }
+
+    /// Whether this provider is usually a server on this machine, which works offline.
+    pub fn is_local(self) -> bool {
+        matches!(self, ProviderKind::Ollama | ProviderKind::OpenAICompatible)
+    }
+
+    /// Whether requests to this provider fail without an API key.
+    pub fn requires_api_key(self) -> bool {
+        matches!(self, ProviderKind::OpenAI | ProviderKind::Anthropic)
+    }
+
+    pub fn default_model(self) -> &'static str {
+        match self {
+            ProviderKind::OpenAI => "gpt-4o-2024-08-06",
+            ProviderKind::Anthropic => "claude-3-5-sonnet-20241022",
+            ProviderKind::Ollama => "llama3.1",
+            // Most single-model servers ignore the model name entirely.
+            ProviderKind::OpenAICompatible => "default",
+        }
+    }
+}
+
+/// Everything needed to construct a provider.
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    pub api_key: Option<String>,
+    pub api_base: Option<String>,
+    pub org_id: Option<String>,
+    pub project_id: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub proxy: Option<String>,
+    pub request_timeout: Duration,
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    /// Whether to use the provider's structured output support, if it has any.
+    pub structured_output: bool,
+    pub retry: RetryPolicy,
+}
+
+/// Builds a provider, sending requests to `fallback` instead when it cannot be reached.
+pub fn build(
+    config: ProviderConfig,
+    fallback: Option<ProviderConfig>,
+) -> Result<Box<dyn CommandGenerator>> {
+    let retry = config.retry;
+    let kind = config.kind;
+    let mut generator = connect(config)?;
+
+    if let Some(fallback) = fallback {
+        let (local_provider, local_model) = (fallback.kind, fallback.model.clone());
+        generator = Box::new(FallbackGenerator::new(
+            generator,
+            kind,
+            connect(fallback)?,
+            local_provider,
+            local_model,
+        ));
+    }
+
+    // Retries go around the fallback, so an unreachable provider is not retried before the local
+    // model is tried.
+    Ok(Box::new(RetryingGenerator::new(generator, retry)))
+}
+
+fn connect(config: ProviderConfig) -> Result<Box<dyn CommandGenerator>> {
+    let http = http_client(&config)?;
+
+    Ok(match config.kind {
+        ProviderKind::OpenAI => Box::new(OpenAIGenerator::new(config, http)),
+        ProviderKind::OpenAICompatible => {
+            if config.api_base.is_none() {
+                anyhow::bail!("The openai-compatible provider requires --api-base to be set.");
+            }
+            Box::new(OpenAIGenerator::new(config, http))
+        }
+        ProviderKind::Anthropic => Box::new(AnthropicGenerator::new(config, http)),
+        ProviderKind::Ollama => Box::new(OllamaGenerator::new(config, http)),
+    })
+}
+
+/// Builds the HTTP client shared by every provider, applying headers, proxy and timeout.
+fn http_client(config: &ProviderConfig) -> Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name '{}'", name))?;
+        let value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header '{}'", name))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .timeout(config.request_timeout);
+
+    if let Some(proxy) = &config.proxy {
+        let proxy =
+            reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy URL '{}'", proxy))?;
+        builder = builder.proxy(proxy);
+    }
+
+    builder.build().context("Unable to build HTTP client")
+}
+
+/// Turns an unsuccessful response into an error, telling authentication failures, rate limiting
+/// and server errors apart from the rest.
+async fn status_error(provider: &str, response: reqwest::Response) -> anyhow::Error {
+    let status = response.status();
+    let retry_after = retry_after(response.headers());
+    let message = response.text().await.unwrap_or_default();
+    let provider = provider.to_string();
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Error::AuthFailed { provider, message }.into()
+        }
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+            provider,
+            message,
+            retry_after,
+        }
+        .into(),
+        status if status.is_server_error() => Error::Unavailable {
+            provider,
+            message: format!("{}: {}", status, message),
+            retry_after,
+        }
+        .into(),
+        _ => anyhow::anyhow!(
+            "Unable to generate command. {} request failed with status {}: {}",
+            provider,
+            status,
+            message
+        ),
+    }
+}
+
+/// Reads a `Retry-After` header given in seconds. The HTTP date form is rare from APIs.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+/// Classifies a failure to send a request or to read its response.
+fn request_error(err: reqwest::Error) -> Error {
+    if err.is_timeout() {
+        return Error::Timeout {
+            setting: "--request-timeout",
+        };
+    }
+
+    // reqwest's own message leaves out the underlying cause, such as a refused connection.
+    // Causes often repeat the message of the cause they wrap, so only new text is added.
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(&err);
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            message.push_str(&format!(": {}", cause_message));
+        }
+        source = cause.source();
+    }
+    Error::Network { message }
+}
+
+/// Calls `f` with each line of a streamed response body.
+async fn for_each_line(
+    response: reqwest::Response,
+    mut f: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut body = response.bytes_stream();
+    let mut buffer = Vec::new();
+
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk
+            .map_err(request_error)
+            .context("Unable to generate command. The response stream failed.")?;
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(newline) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line = buffer.drain(..=newline).collect::<Vec<_>>();
+            f(String::from_utf8_lossy(&line).trim_end())?;
+        }
+    }
+
+    if !buffer.is_empty() {
+        f(String::from_utf8_lossy(&buffer).trim_end())?;
+    }
+
+    Ok(())
+}
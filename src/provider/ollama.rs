@@ -0,0 +1,188 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    for_each_line, request_error, status_error, CommandGenerator, Completion, Message,
+    ProviderConfig, Role, Schema, Usage,
+};
+
+const DEFAULT_API_BASE: &str = "http://localhost:11434";
+
+/// Talks to a local Ollama server's native chat API.
+pub struct OllamaGenerator {
+    http: reqwest::Client,
+    api_base: String,
+    model: String,
+    temperature: f32,
+    max_tokens: u32,
+    structured_output: bool,
+}
+
+impl OllamaGenerator {
+    pub fn new(config: ProviderConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            api_base: config
+                .api_base
+                .unwrap_or_else(|| DEFAULT_API_BASE.to_string()),
+            model: config.model,
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            structured_output: config.structured_output,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<RequestMessage<'a>>,
+    stream: bool,
+    options: Options,
+    /// A JSON schema the reply must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct RequestMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct Options {
+    temperature: f32,
+    num_predict: u32,
+}
+
+/// A full response, or one line of a streamed response.
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: ResponseMessage,
+    /// Token counts, sent with the complete response or the last line of a stream.
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: String,
+}
+
+impl OllamaGenerator {
+    async fn send(
+        &self,
+        messages: &[Message],
+        stream: bool,
+        schema: Option<&Schema>,
+    ) -> Result<reqwest::Response> {
+        let body = ChatRequest {
+            model: &self.model,
+            messages: messages
+                .iter()
+                .map(|message| RequestMessage {
+                    role: match message.role {
+                        Role::System => "system",
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    },
+                    content: &message.content,
+                })
+                .collect(),
+            stream,
+            options: Options {
+                temperature: self.temperature,
+                num_predict: self.max_tokens,
+            },
+            format: schema.map(|schema| &schema.schema),
+        };
+
+        let response = self
+            .http
+            .post(format!("{}/api/chat", self.api_base.trim_end_matches('/')))
+            .json(&body)
+            .send()
+            .await
+            .map_err(request_error)
+            .with_context(|| {
+                format!(
+                    "Unable to generate command. Ollama request to {} failed.",
+                    self.api_base
+                )
+            })?;
+
+        if !response.status().is_success() {
+            return Err(status_error("Ollama", response).await);
+        }
+
+        Ok(response)
+    }
+
+    async fn receive(&self, messages: &[Message], schema: Option<&Schema>) -> Result<Completion> {
+        let response: ChatResponse = self
+            .send(messages, false, schema)
+            .await?
+            .json()
+            .await
+            .context("Unable to generate command. Ollama response was malformed.")?;
+
+        Ok(Completion {
+            content: response.message.content,
+            usage: response.prompt_eval_count.zip(response.eval_count).map(
+                |(input_tokens, output_tokens)| Usage {
+                    input_tokens,
+                    output_tokens,
+                },
+            ),
+        })
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for OllamaGenerator {
+    async fn complete(&self, messages: &[Message]) -> Result<Completion> {
+        self.receive(messages, None).await
+    }
+
+    async fn complete_structured(
+        &self,
+        messages: &[Message],
+        schema: &Schema,
+    ) -> Result<Option<Completion>> {
+        if !self.structured_output {
+            return Ok(None);
+        }
+
+        self.receive(messages, Some(schema)).await.map(Some)
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        on_delta: &mut (dyn for<'a> FnMut(&'a str) + Send),
+    ) -> Result<Completion> {
+        let response = self.send(messages, true, None).await?;
+        let mut content = String::new();
+
+        // Ollama streams one JSON object per line.
+        for_each_line(response, |line| {
+            if line.trim().is_empty() {
+                return Ok(());
+            }
+
+            let chunk: ChatResponse = serde_json::from_str(line)
+                .context("Unable to generate command. Ollama response was malformed.")?;
+            on_delta(&chunk.message.content);
+            content.push_str(&chunk.message.content);
+            Ok(())
+        })
+        .await?;
+
+        Ok(Completion {
+            content,
+            usage: None,
+        })
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use async_openai::error::ApiError;
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+    CreateChatCompletionStreamResponse, ResponseFormat, ResponseFormatJsonSchema,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{
+    for_each_line, request_error, status_error, CommandGenerator, Completion, Message,
+    ProviderConfig, Role, Schema, Usage,
+};
+use crate::error::Error;
+
+const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+
+/// Talks to OpenAI, or to any server implementing its chat completions API.
+///
+/// Requests go through the shared HTTP client, like the other providers, so failures are
+/// classified from the status and headers, `Retry-After` included. The client library is only
+/// used for its request and response types.
+pub struct OpenAIGenerator {
+    http: reqwest::Client,
+    api_key: Option<String>,
+    api_base: String,
+    org_id: Option<String>,
+    project_id: Option<String>,
+    model: String,
+    temperature: f32,
+    max_tokens: u32,
+    structured_output: bool,
+}
+
+impl OpenAIGenerator {
+    pub fn new(config: ProviderConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            api_key: config.api_key,
+            api_base: config
+                .api_base
+                .unwrap_or_else(|| DEFAULT_API_BASE.to_string()),
+            org_id: config.org_id,
+            project_id: config.project_id,
+            model: config.model,
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            structured_output: config.structured_output,
+        }
+    }
+}
+
+/// A streamed chunk, or the error a server sends instead of one.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StreamEvent {
+    Chunk(CreateChatCompletionStreamResponse),
+    Error { error: ApiError },
+}
+
+fn to_request_message(message: &Message) -> ChatCompletionRequestMessage {
+    let content = message.content.as_str();
+
+    match message.role {
+        Role::System => ChatCompletionRequestSystemMessageArgs::default()
+            .content(content)
+            .build()
+            .expect("system message is valid")
+            .into(),
+        Role::User => ChatCompletionRequestUserMessageArgs::default()
+            .content(content)
+            .build()
+            .expect("user message is valid")
+            .into(),
+        Role::Assistant => ChatCompletionRequestAssistantMessageArgs::default()
+            .content(content)
+            .build()
+            .expect("assistant message is valid")
+            .into(),
+    }
+}
+
+impl OpenAIGenerator {
+    fn request(&self, messages: &[Message]) -> CreateChatCompletionRequest {
+        CreateChatCompletionRequestArgs::default()
+            .model(&self.model)
+            .max_tokens(self.max_tokens)
+            .temperature(self.temperature)
+            .messages(messages.iter().map(to_request_message).collect::<Vec<_>>())
+            .build()
+            .expect("request is valid")
+    }
+
+    async fn send(&self, request: &CreateChatCompletionRequest) -> Result<reqwest::Response> {
+        let mut builder = self
+            .http
+            .post(format!(
+                "{}/chat/completions",
+                self.api_base.trim_end_matches('/')
+            ))
+            .json(request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        if let Some(org_id) = &self.org_id {
+            builder = builder.header("OpenAI-Organization", org_id);
+        }
+        if let Some(project_id) = &self.project_id {
+            builder = builder.header("OpenAI-Project", project_id);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(request_error)
+            .context("Unable to generate command. OpenAI request failed.")?;
+
+        if !response.status().is_success() {
+            return Err(status_error("OpenAI", response).await);
+        }
+
+        Ok(response)
+    }
+
+    async fn receive(&self, request: &CreateChatCompletionRequest) -> Result<Completion> {
+        let response: CreateChatCompletionResponse = self
+            .send(request)
+            .await?
+            .json()
+            .await
+            .context("Unable to generate command. OpenAI response was malformed.")?;
+
+        to_completion(response)
+    }
+}
+
+fn to_completion(mut response: CreateChatCompletionResponse) -> Result<Completion> {
+    let choice = response
+        .choices
+        .pop()
+        .context("Unable to generate command. No response from model.")?;
+
+    if let Some(refusal) = choice.message.refusal {
+        return Err(Error::Refused { message: refusal }.into());
+    }
+
+    Ok(Completion {
+        content: choice.message.content.unwrap_or_default(),
+        usage: response.usage.map(|usage| Usage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+        }),
+    })
+}
+
+#[async_trait]
+impl CommandGenerator for OpenAIGenerator {
+    async fn complete(&self, messages: &[Message]) -> Result<Completion> {
+        self.receive(&self.request(messages)).await
+    }
+
+    async fn complete_structured(
+        &self,
+        messages: &[Message],
+        schema: &Schema,
+    ) -> Result<Option<Completion>> {
+        if !self.structured_output {
+            return Ok(None);
+        }
+
+        let mut request = self.request(messages);
+        request.response_format = Some(ResponseFormat::JsonSchema {
+            json_schema: ResponseFormatJsonSchema {
+                description: Some(schema.description.to_string()),
+                name: schema.name.to_string(),
+                schema: Some(schema.schema.clone()),
+                strict: Some(true),
+            },
+        });
+
+        self.receive(&request).await.map(Some)
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        on_delta: &mut (dyn for<'a> FnMut(&'a str) + Send),
+    ) -> Result<Completion> {
+        let mut request = self.request(messages);
+        request.stream = Some(true);
+        let response = self.send(&request).await?;
+        let mut content = String::new();
+
+        for_each_line(response, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(());
+            };
+            if data == "[DONE]" {
+                return Ok(());
+            }
+
+            let event: StreamEvent = serde_json::from_str(data)
+                .context("Unable to generate command. OpenAI response was malformed.")?;
+            let chunk = match event {
+                StreamEvent::Chunk(chunk) => chunk,
+                StreamEvent::Error { error } => {
+                    anyhow::bail!("Unable to generate command. {}", error.message)
+                }
+            };
+
+            let delta = chunk
+                .choices
+                .into_iter()
+                .filter_map(|choice| choice.delta.content)
+                .collect::<String>();
+            on_delta(&delta);
+            content.push_str(&delta);
+            Ok(())
+        })
+        .await?;
+
+        Ok(Completion {
+            content,
+            usage: None,
+        })
+    }
+}
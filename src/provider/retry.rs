@@ -0,0 +1,147 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{CommandGenerator, Completion, Message, ProviderKind, Schema};
+use crate::error;
+
+/// How failed requests are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// How many times a request is sent before giving up, including the first.
+    pub max_attempts: u32,
+    /// The delay before the first retry. It doubles with every attempt after that.
+    pub initial_delay: Duration,
+    /// The longest delay between attempts. A provider that asks for a longer wait is not
+    /// retried.
+    pub max_delay: Duration,
+}
+
+/// Retries requests that failed for reasons that may not last, such as rate limiting, server
+/// errors and dropped connections.
+pub struct RetryingGenerator {
+    inner: Box<dyn CommandGenerator>,
+    policy: RetryPolicy,
+}
+
+impl RetryingGenerator {
+    pub fn new(inner: Box<dyn CommandGenerator>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Waits before the next attempt if a failed one is worth repeating, saying so on stderr.
+    ///
+    /// Returns the error, with the number of attempts made, if it is not.
+    async fn retry(&self, attempt: u32, err: anyhow::Error) -> Result<()> {
+        let transient = error::find(&err).filter(|err| err.is_transient());
+        let Some(transient) = transient else {
+            return Err(err);
+        };
+        if attempt >= self.policy.max_attempts {
+            return Err(match attempt {
+                1 => err,
+                _ => err.context(format!("Gave up after {} attempts.", attempt)),
+            });
+        }
+
+        let delay = match transient.retry_after() {
+            Some(delay) if delay > self.policy.max_delay => {
+                let secs = delay.as_secs_f64();
+                return Err(err.context(format!(
+                    "The provider asked to wait {:.1}s before retrying, longer than retry.max_delay.",
+                    secs
+                )));
+            }
+            Some(delay) => delay,
+            None => self.backoff(attempt),
+        };
+        eprintln!(
+            "Attempt {} of {} failed, retrying in {:.1}s: {}",
+            attempt,
+            self.policy.max_attempts,
+            delay.as_secs_f64(),
+            transient
+        );
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+
+    /// The delay after an attempt: exponential, capped, with jitter so that clients that failed
+    /// together do not retry together.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .policy
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.policy.max_delay);
+
+        // Somewhere between half and all of the delay.
+        delay.mul_f64(0.5 + random_fraction() / 2.0)
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for RetryingGenerator {
+    async fn complete(&self, messages: &[Message]) -> Result<Completion> {
+        let mut attempt = 1;
+        loop {
+            match self.inner.complete(messages).await {
+                Ok(completion) => return Ok(completion),
+                Err(err) => self.retry(attempt, err).await?,
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn complete_structured(
+        &self,
+        messages: &[Message],
+        schema: &Schema,
+    ) -> Result<Option<Completion>> {
+        let mut attempt = 1;
+        loop {
+            match self.inner.complete_structured(messages, schema).await {
+                Ok(completion) => return Ok(completion),
+                Err(err) => self.retry(attempt, err).await?,
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn complete_stream(
+        &self,
+        messages: &[Message],
+        on_delta: &mut (dyn for<'a> FnMut(&'a str) + Send),
+    ) -> Result<Completion> {
+        let mut attempt = 1;
+        loop {
+            let mut streamed = false;
+            let mut on_delta = |delta: &str| {
+                streamed |= !delta.is_empty();
+                on_delta(delta);
+            };
+
+            match self.inner.complete_stream(messages, &mut on_delta).await {
+                Ok(completion) => return Ok(completion),
+                // Part of the command is already on the terminal, and a retry would repeat it.
+                Err(err) if streamed => return Err(err),
+                Err(err) => self.retry(attempt, err).await?,
+            }
+            attempt += 1;
+        }
+    }
+
+    fn fallback(&self) -> Option<(ProviderKind, &str)> {
+        self.inner.fallback()
+    }
+}
+
+/// A random number in `[0, 1)`. `RandomState` is seeded randomly, which is plenty for jitter.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
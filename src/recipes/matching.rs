@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+
+/// Words that change little about what is being asked for.
+const STOP_WORDS: &[&str] = &[
+    "a", "all", "an", "can", "do", "how", "i", "me", "my", "please", "some", "the", "you",
+];
+
+/// How alike two words must be to count as the same word misspelt, from 0 to 1.
+const WORD_SIMILARITY: f64 = 0.75;
+
+/// Words shorter than this must match exactly, as one wrong letter changes them too much. So
+/// must words with digits in them.
+const MIN_FUZZY_LEN: usize = 5;
+
+/// Characters a placeholder's value may contain, which need no quoting in any shell. The value
+/// may not start with `-`, so that it cannot be taken for an option.
+const SAFE_PUNCTUATION: &str = "._/:@%+=,~-";
+
+enum Token<'a> {
+    /// A word in lower case, with the original kept for placeholders to take.
+    Word {
+        text: String,
+        original: &'a str,
+    },
+    Placeholder(&'a str),
+}
+
+/// How well an action matches one of a recipe's actions, from 0 to 1, with the words taken by
+/// its placeholders. `None` if a placeholder is left without a word.
+pub fn score(action: &str, pattern: &str) -> Option<(f64, BTreeMap<String, String>)> {
+    let action = tokenize(action, false);
+    let pattern = tokenize(pattern, true);
+    let (n, m) = (action.len(), pattern.len());
+    if n.max(m) == 0 {
+        return None;
+    }
+
+    // An edit distance over words, where misspelt words cost a little and placeholders nothing.
+    let mut costs = vec![vec![f64::INFINITY; m + 1]; n + 1];
+    costs[0][0] = 0.0;
+    for i in 0..=n {
+        for j in 0..=m {
+            if i > 0 {
+                costs[i][j] = costs[i][j].min(costs[i - 1][j] + 1.0);
+            }
+            if j > 0 {
+                costs[i][j] = costs[i][j].min(costs[i][j - 1] + skip_cost(&pattern[j - 1]));
+            }
+            if i > 0 && j > 0 {
+                let cost = match_cost(&action[i - 1], &pattern[j - 1]);
+                costs[i][j] = costs[i][j].min(costs[i - 1][j - 1] + cost);
+            }
+        }
+    }
+    if costs[n][m].is_infinite() {
+        return None;
+    }
+
+    // Walk back along the cheapest alignment to see which words the placeholders took.
+    let mut captures = BTreeMap::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 {
+            let cost = match_cost(&action[i - 1], &pattern[j - 1]);
+            if costs[i][j] == costs[i - 1][j - 1] + cost {
+                if let (Token::Word { original, .. }, Token::Placeholder(name)) =
+                    (&action[i - 1], &pattern[j - 1])
+                {
+                    captures.insert(name.to_string(), original.to_string());
+                }
+                i -= 1;
+                j -= 1;
+                continue;
+            }
+        }
+        if i > 0 && costs[i][j] == costs[i - 1][j] + 1.0 {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+
+    Some((1.0 - costs[n][m] / n.max(m) as f64, captures))
+}
+
+/// The share of a query's words that appear in `text`, allowing for misspellings.
+pub fn coverage(query: &str, text: &str) -> f64 {
+    let query = tokenize(query, false);
+    let text = tokenize(text, false);
+    if query.is_empty() {
+        return 0.0;
+    }
+
+    let found = query
+        .iter()
+        .filter(|word| text.iter().any(|other| similarity(word, other) > 0.0))
+        .count();
+    found as f64 / query.len() as f64
+}
+
+/// Puts the words taken by placeholders into a command.
+pub fn fill(command: &str, captures: &BTreeMap<String, String>) -> String {
+    captures
+        .iter()
+        .fold(command.to_string(), |command, (name, value)| {
+            command.replace(&format!("{{{{{}}}}}", name), value)
+        })
+}
+
+/// The names of the `{{name}}` placeholders in some text.
+pub fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        names.push(rest[start + 2..start + end].trim());
+        rest = &rest[start + end + 2..];
+    }
+    names
+}
+
+fn tokenize(text: &str, placeholders: bool) -> Vec<Token<'_>> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            let word = word
+                .trim_matches(|c: char| ",;:!?\"'()".contains(c))
+                .trim_end_matches('.');
+            if placeholders {
+                if let Some(name) = word.strip_prefix("{{").and_then(|w| w.strip_suffix("}}")) {
+                    return Some(Token::Placeholder(name.trim()));
+                }
+            }
+
+            let text = word.to_lowercase();
+            (!text.is_empty() && !STOP_WORDS.contains(&text.as_str())).then_some(Token::Word {
+                text,
+                original: word,
+            })
+        })
+        .collect()
+}
+
+/// The cost of an action word standing for a recipe word or placeholder.
+fn match_cost(word: &Token, pattern: &Token) -> f64 {
+    match (word, pattern) {
+        (Token::Word { original, .. }, Token::Placeholder(_)) => {
+            let safe = !original.starts_with('-')
+                && original
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || SAFE_PUNCTUATION.contains(c));
+            if safe {
+                0.0
+            } else {
+                f64::INFINITY
+            }
+        }
+        (Token::Word { .. }, Token::Word { .. }) => 1.0 - similarity(word, pattern),
+        (Token::Placeholder(_), _) => f64::INFINITY,
+    }
+}
+
+/// The cost of a recipe word that is missing from the action. Placeholders must be filled.
+fn skip_cost(pattern: &Token) -> f64 {
+    match pattern {
+        Token::Word { .. } => 1.0,
+        Token::Placeholder(_) => f64::INFINITY,
+    }
+}
+
+/// How alike two words are, or 0 if they are different words.
+fn similarity(a: &Token, b: &Token) -> f64 {
+    let (Token::Word { text: a, .. }, Token::Word { text: b, .. }) = (a, b) else {
+        return 0.0;
+    };
+    if a == b {
+        return 1.0;
+    }
+    // `500mb` is not a misspelling of `100mb`.
+    if a.chars().count() < MIN_FUZZY_LEN
+        || b.chars().count() < MIN_FUZZY_LEN
+        || a.chars().chain(b.chars()).any(|c| c.is_ascii_digit())
+    {
+        return 0.0;
+    }
+
+    let similarity = strsim::normalized_damerau_levenshtein(a, b);
+    if similarity >= WORD_SIMILARITY {
+        similarity
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipes::MATCH_THRESHOLD;
+
+    fn score_of(action: &str, pattern: &str) -> f64 {
+        score(action, pattern).map_or(0.0, |(score, _)| score)
+    }
+
+    #[test]
+    fn scores_exact_and_reworded_actions() {
+        assert_eq!(score_of("Find empty files", "find empty files"), 1.0);
+        assert_eq!(
+            score_of("how do I find all the empty files?", "Find empty files"),
+            1.0
+        );
+        assert!(score_of("find emtpy files", "find empty files") >= MATCH_THRESHOLD);
+        assert!(score_of("find empty directories", "find empty files") < MATCH_THRESHOLD);
+        assert!(score_of("find files", "find empty files") < MATCH_THRESHOLD);
+        assert!(score("", "").is_none());
+    }
+
+    #[test]
+    fn requires_words_with_digits_to_match_exactly() {
+        assert!(
+            score_of(
+                "find files larger than 500MB",
+                "find files larger than 100MB"
+            ) < MATCH_THRESHOLD
+        );
+        assert!(score_of("show ipv4 addresses", "show ipv6 addresses") < MATCH_THRESHOLD);
+        assert_eq!(
+            score_of(
+                "find files larger than 100MB",
+                "find files larger than 100MB"
+            ),
+            1.0
+        );
+    }
+
+    #[test]
+    fn captures_placeholders() {
+        let (value, captures) = score("show the size of src/main.rs", "Show the size of {{path}}")
+            .expect("the placeholder is filled");
+        assert_eq!(value, 1.0);
+        assert_eq!(captures["path"], "src/main.rs");
+
+        // A placeholder must take a word, and only one that needs no quoting.
+        assert!(score("notes.txt", "{{from}} {{to}}").is_none());
+        assert!(score("$(reboot)", "{{path}}").is_none());
+        let switch = score(
+            "switch to branch --discard-changes",
+            "switch to branch {{branch}}",
+        );
+        assert!(switch.is_none_or(|(_, captures)| captures["branch"] != "--discard-changes"));
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        let captures = BTreeMap::from([
+            ("file".to_string(), "notes.txt".to_string()),
+            ("text".to_string(), "TODO".to_string()),
+        ]);
+        assert_eq!(
+            fill("grep -n -- {{text}} {{file}} {{file}}", &captures),
+            "grep -n -- TODO notes.txt notes.txt"
+        );
+        assert_eq!(fill("ls {{path}}", &captures), "ls {{path}}");
+    }
+
+    #[test]
+    fn lists_placeholders() {
+        assert_eq!(placeholders("cp {{from}} {{ to }}"), ["from", "to"]);
+        assert!(placeholders("echo {{unclosed").is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+use std::env;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::dialect::Dialect;
+
+mod matching;
+mod tldr;
+mod yaml;
+
+/// The directory in the data dir holding the user's own recipes.
+pub const RECIPES_DIR: &str = "recipes";
+
+/// How closely an action must match a recipe for the recipe to be used instead of the model,
+/// from 0 to 1. One word in seven may be missing, extra or different.
+const MATCH_THRESHOLD: f64 = 0.85;
+
+/// A canonical command for a common task, used without asking the model.
+pub struct Recipe {
+    /// Ways of asking for the command. `{{name}}` stands for a word taken from the action.
+    pub actions: Vec<String>,
+    /// The command, with `{{name}}` where the words taken from the action go.
+    pub command: String,
+    /// The operating systems the command works on, as in `std::env::consts::OS`. Empty means any.
+    pub os: Vec<String>,
+    /// The dialects the command is valid in.
+    pub shells: Vec<Dialect>,
+    /// Where the recipe came from: a built-in page, or the path of a YAML file.
+    pub source: String,
+}
+
+impl Recipe {
+    /// Checks that every placeholder in the command can be filled from each of the actions.
+    /// Recipes are for POSIX shells unless `shells` says otherwise.
+    fn new(
+        actions: Vec<String>,
+        command: String,
+        os: Vec<String>,
+        shells: Option<Vec<Dialect>>,
+        source: &str,
+    ) -> Result<Recipe> {
+        if actions.is_empty() {
+            anyhow::bail!("A recipe needs at least one action.");
+        }
+        if command.trim().is_empty() {
+            anyhow::bail!("The command of `{}` is empty.", actions[0]);
+        }
+        for action in &actions {
+            if action.contains("{{")
+                && !action
+                    .split_whitespace()
+                    .filter(|word| word.contains("{{"))
+                    .all(|word| word.starts_with("{{") && word.ends_with("}}"))
+            {
+                anyhow::bail!("Placeholders in `{}` must be whole words.", action);
+            }
+            let names = matching::placeholders(action);
+            if let Some(missing) = matching::placeholders(&command)
+                .into_iter()
+                .find(|name| !names.contains(name))
+            {
+                anyhow::bail!(
+                    "The command uses {{{{{}}}}}, which `{}` does not have.",
+                    missing,
+                    action
+                );
+            }
+        }
+
+        Ok(Recipe {
+            actions,
+            command,
+            os: os.iter().map(|os| os.to_lowercase()).collect(),
+            shells: shells.unwrap_or_else(|| vec![Dialect::Sh, Dialect::Bash, Dialect::Zsh]),
+            source: source.to_string(),
+        })
+    }
+
+    /// Whether the command works on this machine, in this dialect.
+    fn applies(&self, dialect: Dialect) -> bool {
+        (self.os.is_empty() || self.os.iter().any(|os| os == env::consts::OS))
+            && self.shells.contains(&dialect)
+    }
+}
+
+/// A recipe that matches an action.
+pub struct Match<'a> {
+    pub recipe: &'a Recipe,
+    /// The command, with the placeholders filled in.
+    pub command: String,
+    pub score: f64,
+}
+
+/// The built-in recipes and the user's own.
+pub struct Recipes {
+    recipes: Vec<Recipe>,
+}
+
+impl Recipes {
+    /// Loads the built-in recipes and the YAML files in `dir`. The user's come first, so they
+    /// win when a built-in recipe matches just as well. Files that cannot be read or parsed are
+    /// skipped with a warning.
+    pub async fn load(dir: &Path) -> Result<Recipes> {
+        let mut paths = Vec::new();
+        match tokio::fs::read_dir(dir).await {
+            Ok(mut entries) => {
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    let extension = path.extension().and_then(|extension| extension.to_str());
+                    if matches!(extension, Some("yaml" | "yml")) {
+                        paths.push(path);
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("Unable to read {}", dir.display()))
+            }
+        }
+        paths.sort();
+
+        // A broken file only loses its own recipes, as the rest still answer their actions.
+        let mut recipes = Vec::new();
+        for path in paths {
+            let source = path.display().to_string();
+            let loaded = match tokio::fs::read_to_string(&path).await {
+                Ok(text) => yaml::recipes(&text, &source),
+                Err(err) => Err(err.into()),
+            };
+            match loaded {
+                Ok(loaded) => recipes.extend(loaded),
+                Err(err) => eprintln!("Warning: skipping the recipes in {}: {:#}", source, err),
+            }
+        }
+        recipes.extend(tldr::builtin());
+
+        Ok(Recipes { recipes })
+    }
+
+    /// The recipes that work on this machine, in this dialect.
+    pub fn applicable(&self, dialect: Dialect) -> impl Iterator<Item = &Recipe> {
+        self.recipes
+            .iter()
+            .filter(move |recipe| recipe.applies(dialect))
+    }
+
+    /// The recipe for an action, if one matches well enough to stand in for the model.
+    pub fn find(&self, action: &str, dialect: Dialect) -> Option<Match<'_>> {
+        let mut best: Option<Match> = None;
+        for recipe in self.applicable(dialect) {
+            for pattern in &recipe.actions {
+                let Some((score, captures)) = matching::score(action, pattern) else {
+                    continue;
+                };
+                if score >= MATCH_THRESHOLD && best.as_ref().is_none_or(|best| score > best.score) {
+                    best = Some(Match {
+                        recipe,
+                        command: matching::fill(&recipe.command, &captures),
+                        score,
+                    });
+                }
+            }
+        }
+        best
+    }
+
+    /// The recipes that mention the words of a query, best first. Placeholders are left as
+    /// they are.
+    pub fn search(&self, query: &str, dialect: Dialect) -> Vec<Match<'_>> {
+        let mut matches = self
+            .applicable(dialect)
+            .filter_map(|recipe| {
+                let text = format!("{} {}", recipe.actions.join(" "), recipe.command);
+                let score = matching::coverage(query, &text);
+                (score > 0.0).then(|| Match {
+                    recipe,
+                    command: recipe.command.clone(),
+                    score,
+                })
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+
+use super::Recipe;
+
+/// Pages compiled into the binary, by platform. `common` pages apply everywhere, the others only
+/// on the operating system of the same name.
+const PAGES: &[(&str, &str, &str)] = &[
+    (
+        "common",
+        "archives",
+        include_str!("pages/common/archives.md"),
+    ),
+    ("common", "files", include_str!("pages/common/files.md")),
+    ("common", "git", include_str!("pages/common/git.md")),
+    ("common", "network", include_str!("pages/common/network.md")),
+    ("linux", "ports", include_str!("pages/linux/ports.md")),
+    ("linux", "system", include_str!("pages/linux/system.md")),
+    ("macos", "ports", include_str!("pages/macos/ports.md")),
+    ("macos", "system", include_str!("pages/macos/system.md")),
+];
+
+/// The recipes compiled into the binary.
+pub fn builtin() -> Vec<Recipe> {
+    PAGES
+        .iter()
+        .flat_map(|(platform, name, page)| {
+            let os = match *platform {
+                "common" => Vec::new(),
+                os => vec![os.to_string()],
+            };
+            recipes(page, os, &format!("built-in/{}/{}", platform, name))
+                .expect("built-in recipes are valid")
+        })
+        .collect()
+}
+
+/// Reads a page in the format of tldr pages: a title, a description, then examples that are
+/// each a description line starting with `- ` and a command in backticks. Several description
+/// lines may share a command, as different ways of asking for it.
+fn recipes(page: &str, os: Vec<String>, source: &str) -> Result<Vec<Recipe>> {
+    let mut recipes = Vec::new();
+    let mut actions = Vec::new();
+
+    for (index, line) in page.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('>') {
+            continue;
+        }
+
+        if let Some(action) = line.strip_prefix("- ") {
+            actions.push(action.trim_end_matches(':').to_string());
+        } else if let Some(command) = line.strip_prefix('`').and_then(|l| l.strip_suffix('`')) {
+            if actions.is_empty() {
+                anyhow::bail!(
+                    "{} line {}: a command needs a description",
+                    source,
+                    index + 1
+                );
+            }
+            recipes.push(Recipe::new(
+                std::mem::take(&mut actions),
+                command.to_string(),
+                os.clone(),
+                None,
+                source,
+            )?);
+        } else {
+            anyhow::bail!("{} line {}: unexpected `{}`", source, index + 1, line);
+        }
+    }
+
+    Ok(recipes)
+}
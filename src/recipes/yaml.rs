@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use super::Recipe;
+use crate::dialect::Dialect;
+
+/// A recipe as written in a YAML file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    action: Strings,
+    command: String,
+    #[serde(default)]
+    os: Option<Strings>,
+    #[serde(default)]
+    shells: Option<Strings>,
+}
+
+/// A value that may be a single string or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Strings {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Strings {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Strings::One(string) => vec![string],
+            Strings::Many(list) => list,
+        }
+    }
+}
+
+/// Reads the recipes in a YAML file: a list of recipes, each with an `action`, a `command` and
+/// optionally `os` and `shells`.
+pub fn recipes(text: &str, source: &str) -> Result<Vec<Recipe>> {
+    // An empty file, or one with only comments, has no recipes rather than being invalid.
+    let entries: Option<Vec<Entry>> = serde_yaml::from_str(text)?;
+
+    entries
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            recipe(entry, source).with_context(|| format!("Invalid recipe {}", index + 1))
+        })
+        .collect()
+}
+
+fn recipe(entry: Entry, source: &str) -> Result<Recipe> {
+    let shells = entry
+        .shells
+        .map(|shells| {
+            shells
+                .into_vec()
+                .iter()
+                .map(|shell| {
+                    Dialect::from_str(shell, true).map_err(|_| {
+                        anyhow::anyhow!(
+                            "Unknown shell `{}`, expected sh, bash, zsh, fish, powershell or nushell.",
+                            shell
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
+
+    // Block scalars such as `command: |` end with a newline.
+    let command = entry.command.trim_end().to_string();
+
+    Recipe::new(
+        entry.action.into_vec(),
+        command,
+        entry.os.map(Strings::into_vec).unwrap_or_default(),
+        shells,
+        source,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        match recipes(text, "test.yaml") {
+            Ok(_) => panic!("expected an error for {:?}", text),
+            Err(err) => format!("{:#}", err),
+        }
+    }
+
+    #[test]
+    fn reads_recipes() {
+        let text = r#"
+# My recipes
+---
+- action: Restart the web server  # a comment
+  command: "sudo systemctl restart nginx"
+  os: linux
+- action:
+    - Tail the log of {{unit}}
+    - 'Don''t stop showing the log of {{unit}}'
+  command: journalctl -fu {{unit}}
+  shells: [bash, zsh, "fish"]
+- {action: [Count the commits], command: 'git rev-list --count HEAD # on this branch'}
+- action: Clean the build
+  command: |
+    make clean
+    rm -rf -- build
+"#;
+        let recipes = recipes(text, "test.yaml").unwrap();
+        assert_eq!(recipes.len(), 4);
+
+        assert_eq!(recipes[0].actions, ["Restart the web server"]);
+        assert_eq!(recipes[0].command, "sudo systemctl restart nginx");
+        assert_eq!(recipes[0].os, ["linux"]);
+        assert_eq!(
+            recipes[0].shells,
+            [Dialect::Sh, Dialect::Bash, Dialect::Zsh]
+        );
+        assert_eq!(recipes[0].source, "test.yaml");
+
+        assert_eq!(
+            recipes[1].actions,
+            [
+                "Tail the log of {{unit}}",
+                "Don't stop showing the log of {{unit}}"
+            ]
+        );
+        assert_eq!(recipes[1].command, "journalctl -fu {{unit}}");
+        assert!(recipes[1].os.is_empty());
+        assert_eq!(
+            recipes[1].shells,
+            [Dialect::Bash, Dialect::Zsh, Dialect::Fish]
+        );
+
+        assert_eq!(recipes[2].actions, ["Count the commits"]);
+        assert_eq!(
+            recipes[2].command,
+            "git rev-list --count HEAD # on this branch"
+        );
+
+        assert_eq!(recipes[3].command, "make clean\nrm -rf -- build");
+    }
+
+    #[test]
+    fn reads_empty_files() {
+        assert!(recipes("", "test.yaml").unwrap().is_empty());
+        assert!(recipes("# nothing yet\n", "test.yaml").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_recipes() {
+        assert!(error("action: x").contains("invalid type"));
+        assert!(error("- action: x\n  comand: y").contains("unknown field `comand`"));
+        assert!(error("- action: x").contains("missing field `command`"));
+        assert!(error("- command: y").contains("missing field `action`"));
+        assert!(error("- action: x\n  command: [a, b]").contains("invalid type"));
+        assert!(error("- action: \"x\n  command: y").contains("line"));
+        assert!(error("- action: x\n  command: y\n  shells: [cmd]").contains("Unknown shell `cmd`"));
+        assert!(error("- action: Show {{path}}\n  command: cat {{file}}")
+            .contains("The command uses {{file}}"));
+        assert!(error("- action: Show x{{path}}\n  command: cat {{path}}")
+            .contains("must be whole words"));
+        assert!(
+            error("- action: x\n  command: y\n- action: z\n  command: ' '")
+                .starts_with("Invalid recipe 2")
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::Error;
+use crate::explain::{tag_contents, Explanation};
+use crate::generate::Generated;
+use crate::provider::Schema;
+
+/// Responses longer than this are cut short in errors.
+const MAX_ERROR_RESPONSE_LEN: usize = 500;
+
+/// Openings that mean the model declined rather than misformatted its answer.
+const REFUSAL_PREFIXES: &[&str] = &[
+    "i can't",
+    "i can’t",
+    "i cannot",
+    "i won't",
+    "i will not",
+    "i'm sorry",
+    "i am sorry",
+    "sorry",
+    "i'm not able",
+    "i am not able",
+    "i'm unable",
+    "i am unable",
+    "as an ai",
+];
+
+/// The schema for providers with structured output. It mirrors the tag protocol, with `null`
+/// standing in for blocks that were not requested.
+pub fn schema() -> Schema {
+    let explanation = json!({
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string" },
+            "parts": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "token": { "type": "string" },
+                        "meaning": { "type": "string" },
+                    },
+                    "required": ["token", "meaning"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+        "required": ["summary", "parts"],
+        "additionalProperties": false,
+    });
+
+    Schema {
+        name: "commands",
+        description: "CLI commands that accomplish the user's action, ranked best first.",
+        schema: json!({
+            "type": "object",
+            "properties": {
+                "commands": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "command": { "type": "string" },
+                            "rationale": { "type": ["string", "null"] },
+                            "explanation": { "anyOf": [explanation, { "type": "null" }] },
+                        },
+                        "required": ["command", "rationale", "explanation"],
+                        "additionalProperties": false,
+                    },
+                },
+                "no_command": { "type": "boolean" },
+            },
+            "required": ["commands", "no_command"],
+            "additionalProperties": false,
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+struct StructuredResponse {
+    #[serde(default)]
+    commands: Vec<Generated>,
+    #[serde(default)]
+    no_command: bool,
+}
+
+/// Extracts the commands from a response, whether it is JSON or tagged text.
+///
+/// Fails with `NoCommand` if the model says the action is impossible, `Refused` if it declined,
+/// and `Unparseable` if no command can be found at all.
+pub fn parse(content: &str, explain: bool) -> Result<Vec<Generated>> {
+    let (generated, no_command) = match parse_json(content) {
+        Some(response) => (response.commands, response.no_command),
+        None => (
+            parse_tags(content, explain),
+            content.contains("<no_command"),
+        ),
+    };
+
+    let mut commands: Vec<Generated> = Vec::new();
+    for mut generated in generated {
+        generated.command = strip_code_fence(&generated.command).to_string();
+        if !explain {
+            generated.explanation = None;
+        }
+
+        if generated.command.is_empty()
+            || commands
+                .iter()
+                .any(|other| other.command == generated.command)
+        {
+            continue;
+        }
+        commands.push(generated);
+    }
+
+    if !commands.is_empty() {
+        return Ok(commands);
+    }
+    if no_command {
+        return Err(Error::NoCommand.into());
+    }
+
+    // Some models ignore the protocol but still put the command in a code block.
+    if let Some(command) = fenced_code_block(content) {
+        return Ok(vec![Generated {
+            command: command.to_string(),
+            rationale: None,
+            explanation: None,
+        }]);
+    }
+
+    let trimmed = content.trim();
+    let lowercase = trimmed.to_lowercase();
+    if REFUSAL_PREFIXES
+        .iter()
+        .any(|prefix| lowercase.starts_with(prefix))
+    {
+        return Err(Error::Refused {
+            message: trimmed.to_string(),
+        }
+        .into());
+    }
+
+    Err(Error::Unparseable {
+        response: truncate(trimmed, MAX_ERROR_RESPONSE_LEN),
+    }
+    .into())
+}
+
+fn parse_json(content: &str) -> Option<StructuredResponse> {
+    let content = strip_code_fence(content);
+    if !content.starts_with('{') {
+        return None;
+    }
+    serde_json::from_str(content).ok()
+}
+
+/// Parses every `<command>` block, along with the rationale and explanation after it.
+///
+/// A missing `</command>` is tolerated: the command then runs up to the next block or the end.
+fn parse_tags(content: &str, explain: bool) -> Vec<Generated> {
+    const OPEN: &str = "<command>";
+    const ENDS: &[&str] = &["</command>", "<rationale>", "<explanation>", "<no_command"];
+
+    // Each command owns the text up to the next command, which holds its rationale and explanation.
+    let starts = content
+        .match_indices(OPEN)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(content.len());
+            let section = &content[start..end];
+
+            let body = &section[OPEN.len()..];
+            let command_end = ENDS
+                .iter()
+                .filter_map(|end| body.find(end))
+                .min()
+                .unwrap_or(body.len());
+
+            Generated {
+                command: body[..command_end].trim().to_string(),
+                rationale: tag_contents(section, "rationale")
+                    .map(|rationale| rationale.trim().to_string()),
+                explanation: if explain {
+                    Explanation::parse(section)
+                } else {
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// Removes a Markdown code fence or inline code backticks wrapped around the whole text.
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+
+    if let Some(fenced) = text
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    {
+        // Drop the language on the opening line, e.g. ```bash
+        return match fenced.split_once('\n') {
+            Some((language, body)) if !language.trim().contains(' ') => body.trim(),
+            _ => fenced.trim(),
+        };
+    }
+
+    match text
+        .strip_prefix('`')
+        .and_then(|rest| rest.strip_suffix('`'))
+    {
+        Some(inline) if !inline.contains('`') => inline.trim(),
+        _ => text,
+    }
+}
+
+/// The contents of the first Markdown code block in the text, if there is a non-empty one.
+fn fenced_code_block(text: &str) -> Option<&str> {
+    let start = text.find("```")?;
+    let end = text[start + 3..].find("```")? + start + 6;
+    let command = strip_code_fence(&text[start..end]);
+    (!command.is_empty()).then_some(command)
+}
+
+fn truncate(text: &str, max_len: usize) -> String {
+    match text.char_indices().nth(max_len) {
+        Some((index, _)) => format!("{}...", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+
+    #[derive(Debug, PartialEq)]
+    enum Expected {
+        Commands(&'static [&'static str]),
+        NoCommand,
+        Refused,
+        Unparseable,
+    }
+
+    const CASES: &[(&str, Expected)] = &[
+        // Tags
+        ("<command>ls -la</command>", Expected::Commands(&["ls -la"])),
+        ("<command>\n  ls -la\n</command>", Expected::Commands(&["ls -la"])),
+        (
+            "<command>ls</command><rationale>short</rationale><command>ls -la</command>",
+            Expected::Commands(&["ls", "ls -la"]),
+        ),
+        // Reversed or duplicate tags
+        ("</command>ls -la<command>", Expected::Unparseable),
+        ("<command><command>ls -la</command>", Expected::Commands(&["ls -la"])),
+        ("<command>ls -la</command></command>", Expected::Commands(&["ls -la"])),
+        (
+            "<command>ls -la</command><command>ls -la</command>",
+            Expected::Commands(&["ls -la"]),
+        ),
+        // A missing </command>
+        ("<command>ls -la", Expected::Commands(&["ls -la"])),
+        (
+            "<command>ls -la\n<rationale>lists everything</rationale>",
+            Expected::Commands(&["ls -la"]),
+        ),
+        (
+            "<command>ls\n<command>ls -la</command>",
+            Expected::Commands(&["ls", "ls -la"]),
+        ),
+        // Fences and backticks
+        ("```\nls -la\n```", Expected::Commands(&["ls -la"])),
+        ("```bash\nls -la\n```", Expected::Commands(&["ls -la"])),
+        (
+            "Try this:\n```sh\ndf -h\n```\nIt shows disk usage.",
+            Expected::Commands(&["df -h"]),
+        ),
+        ("<command>```bash\nls -la\n```</command>", Expected::Commands(&["ls -la"])),
+        ("<command>`ls -la`</command>", Expected::Commands(&["ls -la"])),
+        // JSON
+        (
+            r#"{"commands":[{"command":"ls -la","rationale":null,"explanation":null}],"no_command":false}"#,
+            Expected::Commands(&["ls -la"]),
+        ),
+        (
+            "```json\n{\"commands\":[{\"command\":\"ls -la\",\"rationale\":null,\"explanation\":null}],\"no_command\":false}\n```",
+            Expected::Commands(&["ls -la"]),
+        ),
+        (r#"{"commands":[],"no_command":true}"#, Expected::NoCommand),
+        // No command
+        ("<no_command/>", Expected::NoCommand),
+        ("<no_command />\n```\nfoo\n```", Expected::NoCommand),
+        ("<command></command><no_command/>", Expected::NoCommand),
+        // Refusals
+        ("I can't help with that.", Expected::Refused),
+        ("Sorry, but that would delete your data.", Expected::Refused),
+        ("As an AI, I won't do that.", Expected::Refused),
+        // Plain text
+        ("Here is what you asked for.", Expected::Unparseable),
+        ("", Expected::Unparseable),
+    ];
+
+    /// The commands parsed from a response, or the kind of error it gave.
+    fn outcome(content: &str) -> std::result::Result<Vec<String>, Expected> {
+        match parse(content, false) {
+            Ok(commands) => Ok(commands
+                .into_iter()
+                .map(|generated| generated.command)
+                .collect()),
+            Err(err) => Err(match error::find(&err) {
+                Some(Error::NoCommand) => Expected::NoCommand,
+                Some(Error::Refused { .. }) => Expected::Refused,
+                Some(Error::Unparseable { .. }) => Expected::Unparseable,
+                _ => panic!("unexpected error for {:?}: {:#}", content, err),
+            }),
+        }
+    }
+
+    #[test]
+    fn parses_responses() {
+        for (content, expected) in CASES {
+            match (outcome(content), expected) {
+                (Ok(commands), Expected::Commands(expected)) => {
+                    assert_eq!(commands, *expected, "{:?}", content)
+                }
+                (Err(kind), expected) => assert_eq!(kind, *expected, "{:?}", content),
+                (Ok(commands), expected) => {
+                    panic!("{:?}: expected {:?}, got {:?}", content, expected, commands)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_rationales() {
+        let commands = parse(
+            "<command>du -sh *</command><rationale>sizes of entries</rationale>",
+            false,
+        )
+        .unwrap();
+        assert_eq!(commands[0].rationale.as_deref(), Some("sizes of entries"));
+    }
+
+    #[test]
+    fn truncates_unparseable_responses() {
+        let Err(err) = parse(&"x".repeat(MAX_ERROR_RESPONSE_LEN + 10), false) else {
+            panic!("expected an error");
+        };
+        let Some(Error::Unparseable { response }) = error::find(&err) else {
+            panic!("expected Unparseable, got {:#}", err);
+        };
+        assert_eq!(response.len(), MAX_ERROR_RESPONSE_LEN + 3);
+        assert!(response.ends_with("..."));
+    }
+}
@@ -0,0 +1,152 @@
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::{Command, ExitStatus};
+
+use anyhow::{Context, Result};
+
+const SHELL_ENV_VAR: &str = "SHELL";
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+/// What the user chose to do with a generated command.
+pub enum Confirmation {
+    Run(String),
+    Cancel,
+}
+
+/// Shows the command on stderr and asks the user whether to run, edit or cancel it.
+pub fn confirm(command: &str) -> Result<Confirmation> {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        anyhow::bail!("Unable to confirm command. --run requires an interactive terminal.");
+    }
+
+    let mut command = command.to_string();
+
+    loop {
+        eprintln!("\n  {}\n", command);
+        let answer = prompt("Run this command? [r]un / [e]dit / [c]ancel: ")?;
+
+        match answer.trim().to_lowercase().as_str() {
+            "r" | "run" | "y" | "yes" => return Ok(Confirmation::Run(command)),
+            "e" | "edit" => {
+                let edited = prompt("New command (leave empty to keep the current one): ")?;
+                let edited = edited.trim();
+                if !edited.is_empty() {
+                    command = edited.to_string();
+                }
+            }
+            "" | "c" | "cancel" | "n" | "no" => return Ok(Confirmation::Cancel),
+            other => eprintln!("Unrecognised choice '{}'.", other),
+        }
+    }
+}
+
+/// Lists the commands on stderr and asks the user to pick one, returning its index.
+///
+/// Returns `None` if the user cancels. When stdin is not a terminal the first, best ranked,
+/// command is picked so pipelines keep working.
+pub fn choose(commands: &[(&str, Option<&str>)]) -> Result<Option<usize>> {
+    if !io::stdin().is_terminal() {
+        return Ok(Some(0));
+    }
+
+    eprintln!();
+    for (i, (command, rationale)) in commands.iter().enumerate() {
+        eprintln!("  {}. {}", i + 1, command);
+        if let Some(rationale) = rationale {
+            eprintln!("     {}", rationale);
+        }
+    }
+    eprintln!();
+
+    loop {
+        let answer = prompt(&format!(
+            "Pick a command [1-{}], or press enter to cancel: ",
+            commands.len()
+        ))?;
+        let answer = answer.trim();
+
+        if answer.is_empty() {
+            return Ok(None);
+        }
+
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=commands.len()).contains(&n) => return Ok(Some(n - 1)),
+            _ => eprintln!("Unrecognised choice '{}'.", answer),
+        }
+    }
+}
+
+fn prompt(message: &str) -> Result<String> {
+    // EOF (e.g. Ctrl-D) is treated as a cancellation.
+    Ok(read_line(message)?.unwrap_or_default())
+}
+
+/// Prints a prompt on stderr and reads a line from stdin, returning `None` at EOF.
+pub fn read_line(message: &str) -> Result<Option<String>> {
+    eprint!("{}", message);
+    io::stderr().flush().context("Unable to write prompt")?;
+
+    let mut line = String::new();
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("Unable to read answer from stdin")?;
+
+    Ok((read > 0).then_some(line))
+}
+
+/// Like `read_line`, but what is typed is not echoed when stdin is a terminal.
+pub fn read_secret(message: &str) -> Result<Option<String>> {
+    let hide = cfg!(unix) && io::stdin().is_terminal();
+    if hide {
+        set_echo(false);
+    }
+
+    let line = read_line(message);
+
+    if hide {
+        set_echo(true);
+        // The newline the user typed was not echoed either.
+        eprintln!();
+    }
+    Ok(line?.map(|line| line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn set_echo(echo: bool) {
+    let _ = Command::new("stty")
+        .arg(if echo { "echo" } else { "-echo" })
+        .status();
+}
+
+/// The user's `$SHELL`, falling back to `/bin/sh`.
+pub fn default_shell() -> String {
+    env::var(SHELL_ENV_VAR).unwrap_or_else(|_| FALLBACK_SHELL.to_string())
+}
+
+/// Runs the command in the given shell, streaming its output, and returns its exit code.
+pub fn execute(shell: &str, command: &str) -> Result<i32> {
+    let status = Command::new(shell)
+        .arg("-c")
+        .arg(command)
+        .status()
+        .with_context(|| format!("Unable to spawn shell '{}'", shell))?;
+
+    Ok(exit_code(status))
+}
+
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    // Mirror the shell convention of 128 + signal number for killed processes.
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
@@ -0,0 +1,594 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// How much damage a command could do if it were run blindly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Risk::Low => write!(f, "low"),
+            Risk::Medium => write!(f, "medium"),
+            Risk::High => write!(f, "high"),
+        }
+    }
+}
+
+/// A single destructive pattern found in a command.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub risk: Risk,
+    pub reason: String,
+}
+
+/// The result of analysing a command. The overall risk is the highest risk of any finding.
+#[derive(Clone, Debug)]
+pub struct Assessment {
+    pub risk: Risk,
+    pub findings: Vec<Finding>,
+}
+
+impl Assessment {
+    /// Prints a warning banner to stderr if the command is anything other than low risk.
+    pub fn print_warning(&self) {
+        if self.risk == Risk::Low {
+            return;
+        }
+
+        eprintln!("WARNING: this command is {} risk", self.risk);
+        for finding in &self.findings {
+            eprintln!("  - {}", finding.reason);
+        }
+        eprintln!();
+    }
+}
+
+/// Analyses a shell command for destructive patterns.
+pub fn analyze(command: &str) -> Assessment {
+    let mut findings = Vec::new();
+
+    if contains_fork_bomb(command) {
+        findings.push(Finding {
+            risk: Risk::High,
+            reason: "defines a fork bomb".to_string(),
+        });
+    }
+
+    let segments = split_segments(command);
+
+    for segment in &segments {
+        check_segment(&segment.words, &mut findings);
+    }
+
+    check_remote_script(&segments, command, &mut findings);
+
+    let risk = findings
+        .iter()
+        .map(|finding| finding.risk)
+        .max()
+        .unwrap_or(Risk::Low);
+
+    Assessment { risk, findings }
+}
+
+/// A simple command within a larger command line, along with how it was joined to the previous one.
+struct Segment {
+    words: Vec<String>,
+    piped_from_previous: bool,
+}
+
+/// Splits a command line into simple commands on `|`, `;`, `&&`, `||`, `&` and newlines,
+/// honouring single quotes, double quotes and backslash escapes. The `&` in redirections such
+/// as `2>&1` and `&>` is kept as part of the word.
+fn split_segments(command: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut piped = false;
+    let mut chars = command.chars().peekable();
+
+    fn finish_word(words: &mut Vec<String>, word: &mut String, in_word: &mut bool) {
+        if *in_word {
+            words.push(std::mem::take(word));
+            *in_word = false;
+        }
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                word.push(escaped);
+                            }
+                        }
+                        _ => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            '>' | '<' => {
+                in_word = true;
+                word.push(c);
+                if let Some(ampersand) = chars.next_if_eq(&'&') {
+                    word.push(ampersand);
+                }
+            }
+            '&' if chars.peek() == Some(&'>') => {
+                in_word = true;
+                word.push(c);
+            }
+            '|' | ';' | '&' | '\n' => {
+                finish_word(&mut words, &mut word, &mut in_word);
+                let doubled = chars.peek() == Some(&c) && c != '\n' && c != ';';
+                if doubled {
+                    chars.next();
+                }
+                let is_pipe = c == '|' && !doubled;
+                segments.push(Segment {
+                    words: std::mem::take(&mut words),
+                    piped_from_previous: piped,
+                });
+                piped = is_pipe;
+            }
+            c if c.is_whitespace() => finish_word(&mut words, &mut word, &mut in_word),
+            _ => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+
+    finish_word(&mut words, &mut word, &mut in_word);
+    segments.push(Segment {
+        words,
+        piped_from_previous: piped,
+    });
+
+    segments.retain(|segment| !segment.words.is_empty());
+    segments
+}
+
+/// Commands that run the command in their arguments, with their options that take a value:
+/// the short ones as letters and the long ones by name.
+const PREFIXES: &[(&str, &str, &[&str])] = &[
+    (
+        "sudo",
+        "CDghpRrTtUu",
+        &[
+            "chdir",
+            "chroot",
+            "close-from",
+            "command-timeout",
+            "group",
+            "host",
+            "other-user",
+            "prompt",
+            "role",
+            "type",
+            "user",
+        ],
+    ),
+    ("doas", "Cu", &[]),
+    ("env", "CSu", &["chdir", "split-string", "unset"]),
+    (
+        "xargs",
+        "adEILnPs",
+        &[
+            "arg-file",
+            "delimiter",
+            "max-args",
+            "max-chars",
+            "max-procs",
+            "process-slot-var",
+        ],
+    ),
+    ("nohup", "", &[]),
+    ("exec", "a", &[]),
+    ("command", "", &[]),
+];
+
+/// Returns the words of a simple command with any `sudo`, `env`, `xargs` or `VAR=value`
+/// prefixes removed, along with the options given to them.
+fn strip_prefixes(words: &[String]) -> &[String] {
+    let mut words = words;
+
+    while let Some(first) = words.first() {
+        let is_assignment = first
+            .split_once('=')
+            .is_some_and(|(name, _)| !name.is_empty() && !name.starts_with('-'));
+
+        if is_assignment {
+            words = &words[1..];
+        } else if let Some((_, short, long)) = PREFIXES
+            .iter()
+            .find(|(prefix, _, _)| *prefix == program_name(first))
+        {
+            words = skip_options(&words[1..], short, long);
+        } else {
+            break;
+        }
+    }
+
+    words
+}
+
+/// Skips the options at the start of `words`, and the values of those that take one.
+fn skip_options<'a>(mut words: &'a [String], short: &str, long: &[&str]) -> &'a [String] {
+    while let Some(option) = words.first() {
+        if option == "--" {
+            return &words[1..];
+        }
+
+        let takes_value = if let Some(name) = option.strip_prefix("--") {
+            long.contains(&name)
+        } else if let Some(letters) = option.strip_prefix('-').filter(|l| !l.is_empty()) {
+            // In `-Eu root` the value follows, in `-uroot` it is attached.
+            letters
+                .char_indices()
+                .find(|(_, letter)| short.contains(*letter))
+                .is_some_and(|(index, letter)| index + letter.len_utf8() == letters.len())
+        } else {
+            break;
+        };
+
+        words = &words[1..];
+        if takes_value && !words.is_empty() {
+            words = &words[1..];
+        }
+    }
+
+    words
+}
+
+fn program_name(word: &str) -> &str {
+    word.rsplit('/').next().unwrap_or(word)
+}
+
+fn check_segment(words: &[String], findings: &mut Vec<Finding>) {
+    let words = strip_prefixes(words);
+    let Some((program, args)) = words.split_first() else {
+        return;
+    };
+
+    match program_name(program) {
+        "rm" => check_rm(args, findings),
+        "dd" => check_dd(args, findings),
+        "chmod" => check_chmod(args, findings),
+        "git" => check_git(args, findings),
+        name if name == "mkfs" || name.starts_with("mkfs.") || name == "mke2fs" => {
+            findings.push(Finding {
+                risk: Risk::High,
+                reason: "formats a filesystem with mkfs".to_string(),
+            })
+        }
+        _ => {}
+    }
+
+    check_device_redirect(words, findings);
+}
+
+fn has_short_flag(args: &[String], flag: char) -> bool {
+    args.iter()
+        .any(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg[1..].contains(flag))
+}
+
+fn has_long_flag(args: &[String], flag: &str) -> bool {
+    args.iter().any(|arg| arg == flag)
+}
+
+fn check_rm(args: &[String], findings: &mut Vec<Finding>) {
+    let recursive = has_short_flag(args, 'r')
+        || has_short_flag(args, 'R')
+        || has_long_flag(args, "--recursive");
+    let force = has_short_flag(args, 'f') || has_long_flag(args, "--force");
+
+    if !recursive {
+        return;
+    }
+
+    let targets_root = args.iter().filter(|arg| !arg.starts_with('-')).any(|arg| {
+        let arg = arg.trim_end_matches('/');
+        // `~/*`, `$HOME/*` and `${HOME}/*` are as bad as `/*`.
+        let in_home = ["~", "$HOME", "${HOME}"]
+            .iter()
+            .find_map(|home| arg.strip_prefix(home));
+        matches!(in_home.unwrap_or(arg), "" | "/*") || matches!(arg, "." | ".." | "*")
+    }) || has_long_flag(args, "--no-preserve-root");
+
+    if targets_root {
+        findings.push(Finding {
+            risk: Risk::High,
+            reason: "recursively deletes the root, home or current directory".to_string(),
+        });
+    } else if force {
+        findings.push(Finding {
+            risk: Risk::Medium,
+            reason: "recursively force-deletes files".to_string(),
+        });
+    } else {
+        findings.push(Finding {
+            risk: Risk::Medium,
+            reason: "recursively deletes files".to_string(),
+        });
+    }
+}
+
+fn check_dd(args: &[String], findings: &mut Vec<Finding>) {
+    if args
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("of="))
+        .any(is_device)
+    {
+        findings.push(Finding {
+            risk: Risk::High,
+            reason: "writes directly to a device with dd".to_string(),
+        });
+    }
+}
+
+fn check_chmod(args: &[String], findings: &mut Vec<Finding>) {
+    let world_writable = args
+        .iter()
+        .any(|arg| matches!(arg.as_str(), "777" | "0777" | "a+rwx" | "ugo+rwx" | "o+w"));
+
+    if !world_writable {
+        return;
+    }
+
+    let recursive = has_short_flag(args, 'R') || has_long_flag(args, "--recursive");
+
+    if recursive {
+        findings.push(Finding {
+            risk: Risk::High,
+            reason: "recursively makes files world-writable".to_string(),
+        });
+    } else {
+        findings.push(Finding {
+            risk: Risk::Medium,
+            reason: "makes files world-writable".to_string(),
+        });
+    }
+}
+
+fn check_git(args: &[String], findings: &mut Vec<Finding>) {
+    if args.first().map(String::as_str) != Some("push") {
+        return;
+    }
+
+    let args = &args[1..];
+    let force = has_long_flag(args, "--force")
+        || has_short_flag(args, 'f')
+        || args
+            .iter()
+            .any(|arg| !arg.starts_with('-') && arg.starts_with('+'));
+
+    if force {
+        findings.push(Finding {
+            risk: Risk::High,
+            reason: "force pushes, which can overwrite remote history".to_string(),
+        });
+    } else if args.iter().any(|arg| arg.starts_with("--force-with-lease")) {
+        findings.push(Finding {
+            risk: Risk::Medium,
+            reason: "force pushes with a lease".to_string(),
+        });
+    }
+}
+
+fn check_device_redirect(words: &[String], findings: &mut Vec<Finding>) {
+    let mut words = words.iter().peekable();
+
+    while let Some(word) = words.next() {
+        // `2>`, `&>` and `>>` all redirect, so only what follows the `>` matters.
+        let redirect = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '&');
+        let target = if redirect == ">" || redirect == ">>" {
+            words.peek().map(|next| next.as_str())
+        } else {
+            redirect
+                .strip_prefix(">>")
+                .or_else(|| redirect.strip_prefix('>'))
+        };
+
+        let Some(target) = target else {
+            continue;
+        };
+
+        if is_device(target) {
+            findings.push(Finding {
+                risk: Risk::High,
+                reason: format!("redirects output to the device {}", target),
+            });
+        }
+    }
+}
+
+/// Whether a path is a device that writing to could destroy data. Writing to `/dev/null` or a
+/// terminal is harmless.
+fn is_device(path: &str) -> bool {
+    path.starts_with("/dev/")
+        && !matches!(
+            path,
+            "/dev/null" | "/dev/stdout" | "/dev/stderr" | "/dev/tty"
+        )
+}
+
+const INTERPRETERS: [&str; 9] = [
+    "sh", "bash", "zsh", "dash", "ksh", "fish", "python", "python3", "perl",
+];
+const DOWNLOADERS: [&str; 2] = ["curl", "wget"];
+
+fn check_remote_script(segments: &[Segment], command: &str, findings: &mut Vec<Finding>) {
+    let mut downloading = false;
+    let mut piped_into_shell = false;
+
+    for segment in segments {
+        let words = strip_prefixes(&segment.words);
+        let Some(program) = words.first().map(|word| program_name(word)) else {
+            continue;
+        };
+
+        if !segment.piped_from_previous {
+            downloading = false;
+        }
+
+        if downloading && INTERPRETERS.contains(&program) {
+            piped_into_shell = true;
+        }
+
+        if DOWNLOADERS.contains(&program) {
+            downloading = true;
+        }
+    }
+
+    // Also catch `sh -c "$(curl ...)"` and `bash <(curl ...)`.
+    let substituted = DOWNLOADERS.iter().any(|downloader| {
+        command.contains(&format!("$({}", downloader))
+            || command.contains(&format!("<({}", downloader))
+    }) && segments.iter().any(|segment| {
+        strip_prefixes(&segment.words)
+            .first()
+            .is_some_and(|program| INTERPRETERS.contains(&program_name(program)))
+    });
+
+    if piped_into_shell || substituted {
+        findings.push(Finding {
+            risk: Risk::High,
+            reason: "downloads a script and executes it without review".to_string(),
+        });
+    }
+}
+
+/// Detects the classic `:(){ :|:& };:` fork bomb and renamed variants of it.
+fn contains_fork_bomb(command: &str) -> bool {
+    let compact: String = command.chars().filter(|c| !c.is_whitespace()).collect();
+
+    compact.match_indices("(){").any(|(index, _)| {
+        let name_start = compact[..index]
+            .rfind([';', '&', '|', '{', '}'])
+            .map_or(0, |i| i + 1);
+        let name = &compact[name_start..index];
+
+        !name.is_empty() && compact[index..].starts_with(&format!("(){{{0}|{0}&", name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASES: &[(&str, Risk)] = &[
+        // rm
+        ("rm -rf /", Risk::High),
+        ("rm -rf /*", Risk::High),
+        ("rm -rf ~", Risk::High),
+        ("rm -rf ~/", Risk::High),
+        ("rm -rf ~/*", Risk::High),
+        ("rm -rf \"$HOME\"/*", Risk::High),
+        ("rm -rf ${HOME}/*", Risk::High),
+        ("rm -rf .", Risk::High),
+        ("rm -rf *", Risk::High),
+        ("rm -r --no-preserve-root /", Risk::High),
+        ("rm -rf ~/build", Risk::Medium),
+        ("rm -r build", Risk::Medium),
+        ("rm notes.txt", Risk::Low),
+        // dd
+        ("dd if=image.iso of=/dev/sda bs=4M", Risk::High),
+        ("dd if=/dev/zero of=/dev/null count=1", Risk::Low),
+        ("dd if=/dev/urandom of=random.bin count=1", Risk::Low),
+        // mkfs
+        ("mkfs.ext4 /dev/sdb1", Risk::High),
+        ("mkfs -t ext4 /dev/sdb1", Risk::High),
+        ("/sbin/mke2fs /dev/sdb1", Risk::High),
+        // chmod
+        ("chmod -R 777 /var/www", Risk::High),
+        ("chmod 777 script.sh", Risk::Medium),
+        ("chmod 644 notes.txt", Risk::Low),
+        // Fork bombs
+        (":(){ :|:& };:", Risk::High),
+        ("bomb(){ bomb|bomb& };bomb", Risk::High),
+        // Remote scripts
+        ("curl -fsSL https://example.com/install.sh | sh", Risk::High),
+        (
+            "curl -fsSL https://example.com/install.sh 2>&1 | sh",
+            Risk::High,
+        ),
+        (
+            "curl https://example.com/install.sh | sudo -E bash",
+            Risk::High,
+        ),
+        (
+            "wget -qO- https://example.com/i.sh | sudo -u root bash -s",
+            Risk::High,
+        ),
+        (
+            "sh -c \"$(curl -fsSL https://example.com/install.sh)\"",
+            Risk::High,
+        ),
+        ("bash <(curl -s https://example.com/install.sh)", Risk::High),
+        (
+            "curl -o install.sh https://example.com/install.sh",
+            Risk::Low,
+        ),
+        ("curl -s https://example.com | grep title", Risk::Low),
+        ("curl https://example.com; sh build.sh", Risk::Low),
+        // Force pushes
+        ("git push --force origin main", Risk::High),
+        ("git push -f", Risk::High),
+        ("git push origin +main", Risk::High),
+        ("git push --force-with-lease", Risk::Medium),
+        ("git push origin main", Risk::Low),
+        // Redirections
+        ("echo hello > /dev/sda", Risk::High),
+        ("cat image >/dev/sdb", Risk::High),
+        ("make &>/dev/sda", Risk::High),
+        ("echo hello > /dev/null", Risk::Low),
+        ("ls missing 2>/dev/null", Risk::Low),
+        ("make 2>&1 | tee build.log", Risk::Low),
+        ("sleep 10 & echo started", Risk::Low),
+        // Prefixes
+        ("sudo rm -rf /", Risk::High),
+        ("sudo -u root rm -rf /", Risk::High),
+        ("sudo -E -H rm -rf /", Risk::High),
+        ("sudo --user=root rm -rf /", Risk::High),
+        ("doas -u root rm -rf ~", Risk::High),
+        ("env -u TERM rm -rf /", Risk::High),
+        ("LANG=C sudo -- rm -rf /", Risk::High),
+        ("find . -name '*.tmp' | xargs rm -rf", Risk::Medium),
+        ("find . -print0 | xargs -0 -n 1 rm -rf", Risk::Medium),
+        ("sudo apt update", Risk::Low),
+    ];
+
+    #[test]
+    fn rates_commands() {
+        for (command, risk) in CASES {
+            assert_eq!(analyze(command).risk, *risk, "{}", command);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use clap::ValueEnum;
+
+/// A shell that `howto shell-init` can emit a widget for.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+// Each widget takes the current line as the action and replaces it with the generated command,
+// leaving the line untouched if howto fails. stdin comes from the terminal so `-n` can prompt,
+// and the format is fixed to text so `output.format = "json"` in the config file does not apply.
+
+const BASH: &str = r#"_howto_widget() {
+    [[ -z "$READLINE_LINE" ]] && return
+    local command
+    command="$(howto --shell bash --format text -- "$READLINE_LINE" </dev/tty)" || return
+    [[ -z "$command" ]] && return
+    READLINE_LINE="$command"
+    READLINE_POINT=${#READLINE_LINE}
+}
+bind -x '"\C-g": _howto_widget'
+"#;
+
+const ZSH: &str = r#"_howto_widget() {
+    [[ -z "$BUFFER" ]] && return
+    local command
+    zle -I
+    command="$(howto --shell zsh --format text -- "$BUFFER" </dev/tty)"
+    if [[ $? -eq 0 && -n "$command" ]]; then
+        BUFFER="$command"
+        CURSOR=${#BUFFER}
+    fi
+    zle reset-prompt
+}
+zle -N _howto_widget
+bindkey '^G' _howto_widget
+"#;
+
+const FISH: &str = r#"function _howto_widget
+    set -l action (commandline)
+    test -z "$action"; and return
+    set -l command (howto --shell fish --format text -- "$action" </dev/tty | string collect)
+    if test $pipestatus[1] -eq 0 -a -n "$command"
+        commandline --replace -- $command
+    end
+    commandline -f repaint
+end
+bind \cg _howto_widget
+"#;
+
+/// The script that defines the widget and binds it to Ctrl-G.
+pub fn script(shell: InitShell) -> &'static str {
+    match shell {
+        InitShell::Bash => BASH,
+        InitShell::Zsh => ZSH,
+        InitShell::Fish => FISH,
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::{self, Write};
+
+const OPEN: &str = "<command>";
+const CLOSE: &str = "</command>";
+
+/// Prints the contents of the first `<command>` block to stdout as a streamed response arrives.
+///
+/// Text that might be the start of the closing tag, and whitespace that might turn out to be
+/// trailing, is held back until the next piece arrives so only the trimmed command is printed.
+#[derive(Default)]
+pub struct CommandPrinter {
+    buffer: String,
+    /// How far into the body of the command block has been printed or skipped.
+    consumed: usize,
+    printed: String,
+    done: bool,
+}
+
+impl CommandPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, delta: &str) {
+        if self.done {
+            return;
+        }
+
+        self.buffer.push_str(delta);
+
+        let Some(open) = self.buffer.find(OPEN) else {
+            return;
+        };
+        let body = &self.buffer[open + OPEN.len()..];
+
+        let end = match body.find(CLOSE) {
+            Some(end) => {
+                self.done = true;
+                end
+            }
+            None => body.len() - partial_close_len(body),
+        };
+        let end = end.max(self.consumed);
+
+        let mut text = &body[self.consumed..end];
+        if self.printed.is_empty() {
+            let trimmed = text.trim_start();
+            self.consumed += text.len() - trimmed.len();
+            text = trimmed;
+        }
+        let text = text.trim_end();
+
+        if !text.is_empty() {
+            print!("{}", text);
+            let _ = io::stdout().flush();
+            self.printed.push_str(text);
+            self.consumed += text.len();
+        }
+    }
+
+    /// Everything printed so far.
+    pub fn printed(&self) -> &str {
+        &self.printed
+    }
+}
+
+/// The length of the longest suffix of `text` that is a prefix of the closing tag.
+fn partial_close_len(text: &str) -> usize {
+    (1..CLOSE.len())
+        .rev()
+        .find(|&len| text.ends_with(&CLOSE[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASES: &[(&str, &str)] = &[
+        ("<command>ls -la</command>", "ls -la"),
+        (
+            "Sure:\n<command>\n  du -sh *  \n</command>\n<rationale>sizes</rationale>",
+            "du -sh *",
+        ),
+        ("<command>tar -xzf   a.tgz</command>", "tar -xzf   a.tgz"),
+        ("<command>echo '</com' done</command>", "echo '</com' done"),
+        ("<command>echo ✓ ok</command>", "echo ✓ ok"),
+        ("<command>ls</command><command>pwd</command>", "ls"),
+        ("<command>ls -la", "ls -la"),
+        ("no command here", ""),
+    ];
+
+    /// Splits text into pieces of about `size` bytes, keeping characters whole.
+    fn chunks(text: &str, size: usize) -> Vec<&str> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < text.len() {
+            let mut end = (start + size).min(text.len());
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            pieces.push(&text[start..end]);
+            start = end;
+        }
+        pieces
+    }
+
+    #[test]
+    fn prints_the_trimmed_command_however_it_is_split() {
+        for (reply, command) in CASES {
+            for size in 1..=3 {
+                let mut printer = CommandPrinter::new();
+                for piece in chunks(reply, size) {
+                    printer.push(piece);
+                }
+                assert_eq!(
+                    printer.printed(),
+                    *command,
+                    "{:?} in {}-byte chunks",
+                    reply,
+                    size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn holds_back_a_closing_tag_split_across_chunks() {
+        let mut printer = CommandPrinter::new();
+        printer.push("<command>ls -la </com");
+        assert_eq!(printer.printed(), "ls -la");
+        printer.push("mand> and more");
+        assert_eq!(printer.printed(), "ls -la");
+    }
+}